@@ -2,21 +2,108 @@
 
 /*
 Self-explanatory Arpeggiator for the Novation Launchpad
-Works by storing 32 columns with the ability for users to interact
+Works by storing up to 32 columns with the ability for users to interact
 with each button on the device. The program will send MIDI OUT
 notes to PortMIDI for other programs to pick it up.
 
  * 4 buttons to control the view of the 32-column array
  * play/pause buttons to stop or start
- * major/minor button to swap harmonic scales
+ * holding play while playing stutters: the selected lane's current
+   step repeats in quick succession, and it moves on again on release
+ * scale button to cycle through the major/minor, modal and pentatonic
+   scales (tap it to cycle, hold it as a shift key for secondary
+   functions)
  * quit button
  * octave control on the right-most column
+ * shift + right-most column rows 0/1 moves the root up/down
+ * shift + right-most column rows 2/3 transposes everything up/down
+   a semitone (notes pushed outside the MIDI range are skipped)
+ * shift + right-most column row 4 toggles the metronome
+ * shift + right-most column row 5 cycles swing: straight, 54%,
+   58%, 62% and triplet (lit green through red while shift
+   is held)
+ * shift + right-most column row 6 hands the grid to the next lane
+ * shift + bottom of the right-most column cycles what it does:
+   octave select, edit (row 0 randomizes the pattern, row 1 reverses it,
+   row 2 turns it upside down, rows 3/4 rotate it left/right a step,
+   row 5 copies the view onto the next one, row 6 cycles the step
+   between quarters, eighths, eighth triplets and sixteenths),
+   mute and solo
+   (each button toggles the pitch row beside it), fader (sends a
+   control change, higher rows send higher values), bend (a
+   pitch-bend strip, the fourth row down is no bend), tempo (a
+   bar that rises with the BPM, following tap tempo and the nudges)
+   length (each row down plays four steps fewer, from 32 at the top)
+   and program (sends program changes 0-7 from the top row down on
+   the selected lane's channel, lighting the last one sent)
+ * shift + first view button toggles hold (latches the pattern)
+ * shift + second view button arms recording: grid presses and
+   notes played into the MIDI input (snapped to the scale) are
+   written into the column under the tracker as it passes
+ * shift + third view button cycles direction (up/down/updown/random)
+ * shift + fourth view button taps in a tempo
+ * shift + pause/play nudges the tempo down/up by 1 BPM
+ * shift + quit is a panic button: all notes off on the output
+ * while shift is held the top row shows the secondary functions
+ * shift + grid press sets that column's velocity (top row loudest),
+   except on the bottom row, which toggles an accent on the column
+   (accented steps play at full velocity, marked dim red on the
+   bottom row)
  * 64 buttons to allow users to select 0-7 on each column
- * bottom-row will set the column to 0
+ * bottom-row clears the column
  * bottom-row is also lit up as a "tracker"
+ * pads flash green for a moment as their column plays
+
+Run with `--grid <name>` and `--out <name>` to pick the Launchpad and
+the MIDI output port (defaults "Launchpad MIDI 1" and "Midi Through
+Port-0"), and `--bpm <n>` to set the starting tempo.
+`--export <file.mid>` to save the pattern as a Standard
+MIDI File on quit, for use in a DAW. `--save <file>` keeps the pattern
+(plus scale, root and octave) as text on quit, and `--load <file>`
+brings it back on startup. `--channel <0-15>` picks the MIDI
+channel notes are sent on (default 0). `--chord` plays each column
+as a triad built from the current scale. `--model <mk1|mk2|mk3>`
+picks the LED color scheme for the attached Launchpad (default mk1).
+`--euclid <hits>,<steps>[,<value>]` starts with an Euclidean rhythm.
+`--steps <v,v,...>` sets the first columns directly (0 is a rest, 1-7 a
+scale degree), and the pattern is printed this way on quit.
+`--position` prints the bar and beat (a bar being one pass of the
+pattern) on every beat while playing. `--metronome` starts with the
+click on, and `--click <channel>,<note>` picks what it plays (default
+9,37: a rim click on the GM drum channel).
+`--cc <n>` sets the controller the fader mode sends (default 1, the
+mod wheel). `--pressure-cc <n>` passes aftertouch, from the grid's
+pads or the MIDI input, on as that controller (e.g. 74 for filter
+cutoff).
+`--humanize <ms>` pushes each step late by a random amount up to the
+given milliseconds. `--gate <min>[,<max>]` sets how much of each step a
+note sounds for (0.0-1.0, default 1.0), picked at random between the two
+every step when a max is given. `--max-voices <n>` caps how many notes
+each lane holds at once, releasing the oldest to make room (default 0,
+no limit). `--velocity-curve <linear|exp|log>` bends column
+velocities on the way out (default linear).
+Built with the `osc` feature, `--osc-out <host:port>` mirrors every
+note as an OSC `/note <pitch> <velocity>` bundle, e.g. for visuals,
+and `--osc-in <host:port>` listens for `/play`, `/pause`, `/bpm <n>`
+and `/scale <n>` to drive the arp remotely.
+`--lane <channel>,<step>[,<octave>]` adds another pattern lane
+playing alongside the first (can be repeated). Its step is a
+subdivision name, or ticks at 64 a quarter (32 is an eighth
+note). `--subdivision <quarter|eighth|triplet|sixteenth>` sets the
+first lane's step (default eighth). `--chain <view>,<view>,...`
+plays the four views as separate 8-step patterns in the given order,
+e.g. `--chain 0,0,1,2` for an A A B C arrangement. `--seed <n>` makes
+random patterns, probabilities and humanizing repeatable.
+Any option given a bad value is reported by name, all of them at once,
+before either device is opened.
+
+`--clock-out` sends MIDI clock (24 pulses a beat) with start and stop
+on play and pause, so other gear can follow the arp.
+`--clock-in` does the opposite: while MIDI clock arrives on the output
+port's input, steps follow it instead of the internal tempo.
 
 Most of the functionality here relies on the use of "MidiRes",
-a special Result<(), pm::Error> type alias simply because every
+a special Result<(), MidiError> type alias simply because every
 read and write from a device can potentially fail for random reasons.
 Using the bubble '?' operator alleviates some pains, but mostly
 anything that involves sending or receiving MIDI information can
@@ -24,118 +111,154 @@ generate a Result<T,E> type as a result.
 
 TODOs (4/22/2025):
  * documentation
- * split code up into reusable components for future use
 
 */
 
-use std::thread;
 use std::time::{Duration, Instant};
 
 extern crate portmidi as pm;
 
 extern crate instruments as src;
 use src::devices::device::*;
+use src::devices::grid::*;
+use src::devices::launchpad::*;
+use src::devices::mock::*;
+#[cfg(feature = "osc")]
+use src::osc::{OscArg, OscListener, OscSender};
+use src::patterns::*;
+use src::rng::*;
+use src::scheduler::*;
+use src::smf::{self, SmfEvent};
 use src::types::*;
 
-/// A generic Job container shim to be stored in the scheduler
-#[derive(Debug)]
-pub struct Job<T> {
-    ct: usize,
-    mt: usize,
-    msg: T,
-}
+pub type MidiVal = u8;
+pub type BtnArr = [u8; 4];
 
-/// A Scheduler layout. Contains tick rate, tick duration, timing
-/// and the jobs/queue system.
-pub struct Scheduler<T> {
-    tick_duration: Duration,
-    last_time: Instant,
-    jobs: Vec<Job<T>>,
-    queue: Vec<T>,
+/// Order in which the arpeggiator walks the pattern
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Direction {
+    Up,
+    Down,
+    UpDown,
+    Random,
 }
 
-/// Scheduler implementation. The item to be used must implement Copy
-/// For debugging, add `+ std::fmt::Debug`
-impl<T: Copy> Scheduler<T> {
-    /// Create a new scheduler with job and queue capacities at 100
-    pub fn new() -> Scheduler<T> {
-        let jobs = Vec::with_capacity(100);
-        let queue = Vec::with_capacity(100);
-        Scheduler {
-            tick_duration: Duration::new(0, 0),
-            last_time: Instant::now(),
-            jobs: jobs,
-            queue: queue,
+impl Direction {
+    /// The direction that follows this one when cycling
+    fn next(&self) -> Direction {
+        match self {
+            Direction::Up => Direction::Down,
+            Direction::Down => Direction::UpDown,
+            Direction::UpDown => Direction::Random,
+            Direction::Random => Direction::Up,
         }
     }
+}
 
-    /// Check if the queue has events waiting
-    pub fn has_events(&self) -> bool {
-        self.queue.len() > 0
-    }
+/// What the right-most (side) column does when pressed
+/// Octave picks the octave, Edit holds one-shot pattern operations,
+/// Mute and Solo toggle the pitch row beside each button,
+/// Fader sends a control change like a coarse 8-step slider,
+/// Bend is a pitch-bend strip centered on the fourth row,
+/// Tempo only shows the BPM as a bar rising from the bottom,
+/// Length sets the pattern length four steps per row,
+/// Program sends program changes 0-7, one per row from the top
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum SideMode {
+    Octave,
+    Edit,
+    Mute,
+    Solo,
+    Fader,
+    Bend,
+    Tempo,
+    Length,
+    Program,
+}
 
-    /// Clear the job queue
-    pub fn clear_queue(&mut self) {
-        // delete all items from queue
-        self.queue.clear();
+impl SideMode {
+    /// The side mode that follows this one when cycling
+    fn next(&self) -> SideMode {
+        match self {
+            SideMode::Octave => SideMode::Edit,
+            SideMode::Edit => SideMode::Mute,
+            SideMode::Mute => SideMode::Solo,
+            SideMode::Solo => SideMode::Fader,
+            SideMode::Fader => SideMode::Bend,
+            SideMode::Bend => SideMode::Tempo,
+            SideMode::Tempo => SideMode::Length,
+            SideMode::Length => SideMode::Program,
+            SideMode::Program => SideMode::Octave,
+        }
     }
+}
 
-    /// Schedule a job to be executed every N ticks
-    pub fn interval(&mut self, tick_amt: usize, msg: T) {
-        self.jobs.push(Job {
-            ct: 0,
-            mt: tick_amt,
-            msg: msg,
-        })
+/// Note lengths a lane's step can be set to from the device
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Subdivision {
+    Quarter,
+    Eighth,
+    EighthTriplet,
+    Sixteenth,
+}
+
+impl Subdivision {
+    /// Scheduler ticks in one step of this length
+    fn ticks(&self) -> usize {
+        let beat = TICKS_PER_BEAT as usize;
+        match self {
+            Subdivision::Quarter => beat,
+            Subdivision::Eighth => beat / 2,
+            Subdivision::EighthTriplet => beat / 3,
+            Subdivision::Sixteenth => beat / 4,
+        }
     }
 
-    /// Calculate a schedule rate based on BPM against microseconds
-    /// Start with a minute (in us), divide by ticks x BPM
-    pub fn set_rate(&mut self, bpm: i32, num_ticks: i32) {
-        let ms = 60000000.0 / (bpm * num_ticks) as f64;
-        self.tick_duration = Duration::from_micros(ms as u64);
+    /// The subdivision a step length matches, if any
+    fn from_ticks(ticks: usize) -> Option<Subdivision> {
+        [
+            Subdivision::Quarter,
+            Subdivision::Eighth,
+            Subdivision::EighthTriplet,
+            Subdivision::Sixteenth,
+        ]
+        .into_iter()
+        .find(|sub| sub.ticks() == ticks)
     }
 
-    /// Update will increase the ticks by one
-    /// In order to make sure we are sleeping the thread consistently,
-    /// we need to calculate our current timestamps to ensure
-    /// we can wait a correct amount of time. To do this we calculate
-    /// a delta and sleep for the delta, which will keep us in lockstep
-    /// with our target BPM, to ensure all jobs are executed
-    /// correctly with their respective time measures.
-    pub fn update(&mut self) {
-        for job in &mut self.jobs {
-            job.ct += 1;
-            if job.ct == job.mt {
-                job.ct = 0;
-                self.queue.push(job.msg);
-            }
+    /// Parse a subdivision from its command line name
+    fn from_name(name: &str) -> Option<Subdivision> {
+        match name {
+            "quarter" => Some(Subdivision::Quarter),
+            "eighth" => Some(Subdivision::Eighth),
+            "triplet" => Some(Subdivision::EighthTriplet),
+            "sixteenth" => Some(Subdivision::Sixteenth),
+            _ => None,
         }
-        // trigger a thread sleep HERE
-        let new_time = Instant::now();
-        let elapsed = new_time.duration_since(self.last_time);
-        let delta = self.tick_duration - elapsed;
-        thread::sleep(delta);
-        self.last_time = Instant::now();
-        // end sleep calculation
     }
-}
 
-pub type MidiVal = u8;
-pub type BtnArr = [u8; 4];
-
-// heptatonic scales only (7 notes per octave)
-#[derive(Debug, Copy, Clone)]
-pub enum Scale {
-    Major,
-    Minor,
+    /// The next shorter subdivision, wrapping back to quarters
+    fn next(&self) -> Subdivision {
+        match self {
+            Subdivision::Quarter => Subdivision::Eighth,
+            Subdivision::Eighth => Subdivision::EighthTriplet,
+            Subdivision::EighthTriplet => Subdivision::Sixteenth,
+            Subdivision::Sixteenth => Subdivision::Quarter,
+        }
+    }
 }
 
 #[derive(Debug, Copy, Clone)]
 pub enum Msg {
     CheckInputs,
-    UpdateState,
-    FlushNotes,
+    UpdateState(usize),
+    FlushNotes(usize),
+    NoteOn(usize, MidiVal, MidiVal),
+    NoteOff(usize, MidiVal),
+    Clock,
+    Click,
+    ClickOff(MidiVal),
+    FlashOff(usize),
     Quit,
 }
 
@@ -143,63 +266,149 @@ pub enum Msg {
 // I often forget
 const MIDI: MidiVal = 0xB0;
 const NOTE: MidiVal = 0x90;
+const NOTE_OFF: MidiVal = 0x80;
 
-// Major: C D E F G A B
-// Minor: C D Ef F G Af Bf
-const MAJOR_SCALE: [u8; 7] = [0, 2, 4, 5, 7, 9, 11];
-const MINOR_SCALE: [u8; 7] = [0, 2, 3, 5, 7, 8, 10];
-
-/// Convert a MIDI note and a Scale to a scale-based MIDI message
-/// Uses LUTs to convert to either Major or Minor scale
-fn calc_note(note: MidiVal, scale: &Scale) -> Option<MidiVal> {
-    match (note, scale) {
-        (1..7, Scale::Major) => Some(MAJOR_SCALE[note as usize]),
-        (1..7, Scale::Minor) => Some(MINOR_SCALE[note as usize]),
-        _ => None,
-    }
-}
+// Most steps a pattern can hold: four views of eight columns
+const MAX_STEPS: usize = 32;
+
+// Side column row that sends no pitch bend in Bend mode
+const BEND_CENTER: u8 = 3;
+
+// Quarter notes in a bar, for accenting the metronome's downbeat
+const BEATS_PER_BAR: usize = 4;
+
+// How long a metronome click is held, in ticks
+const CLICK_TICKS: usize = 6;
+
+// Furthest the whole pattern can be transposed, in semitones
+const MAX_TRANSPOSE: i8 = 24;
+
+// Side column rows (from the top) with an action in Edit mode
+const EDIT_ACTIONS: u8 = 7;
+
+// How long a pad lights up brighter when its column plays
+const FLASH_TICKS: usize = 12;
+
+// Velocity of accented steps, whatever the column's own velocity
+const ACCENT_VEL: MidiVal = 127;
 
-/// Converts a MIDI message from 0..127 to (x, y)
-/// where (x,y) correspond to the MIDI device output
-/// Returns None when MIDI value is out of range
-///
-/// find_lp_xy(50) -> Some((3, 5))
-/// find_lp_xy(200) -> None
-fn find_lp_xy(x: MidiVal) -> Option<(u8, u8)> {
-    let nx = match x >= 16 {
-        true => x % 16,
-        _ => x,
+// How many times a step repeats while the stutter is held
+const STUTTER_REPEATS: usize = 4;
+
+// Swing presets cycled from the device: straight, 54%, 58%, 62%
+// and triplet feel, as the share of each pair of steps given to the first
+const SWING_PRESETS: [f64; 5] = [0.5, 0.54, 0.58, 0.62, 2.0 / 3.0];
+
+// Scheduler ticks per quarter note, divisible by 3 for triplets
+const TICKS_PER_BEAT: i32 = 96;
+
+// Ticks per quarter note that `--lane` step lengths are given in,
+// kept from before the scheduler went up to 96
+const LANE_TICKS_PER_BEAT: usize = 64;
+
+// MIDI real-time messages
+const CLOCK: MidiVal = 0xF8;
+const START: MidiVal = 0xFA;
+const STOP: MidiVal = 0xFC;
+
+// MIDI clock pulses per quarter note
+const CLOCK_PPQN: usize = 24;
+
+// Without an incoming clock pulse for this long, external sync
+// is considered lost and the internal scheduler takes over again
+const CLOCK_TIMEOUT: Duration = Duration::from_millis(500);
+
+// Tempo range reachable from the device
+const MIN_BPM: u16 = 40;
+const MAX_BPM: u16 = 300;
+
+// Taps further apart than this start a new tap-tempo measurement
+const TAP_TIMEOUT: Duration = Duration::from_secs(2);
+
+// How long to keep trying to reopen devices after an I/O error,
+// and how long to wait between attempts
+const RECONNECT_TIMEOUT: Duration = Duration::from_secs(5);
+const RECONNECT_RETRY: Duration = Duration::from_millis(500);
+
+/// The LED color used by the scale button for a given scale
+fn scale_led(scale: &Scale, grid: &dyn Grid) -> u8 {
+    let (red, green) = match scale {
+        Scale::Major => (1, 3),
+        Scale::Dorian => (0, 3),
+        Scale::Phrygian => (2, 3),
+        Scale::Lydian => (0, 1),
+        Scale::Mixolydian => (3, 3),
+        Scale::Minor => (3, 1),
+        Scale::Locrian => (3, 0),
+        Scale::MajorPentatonic => (0, 2),
+        Scale::MinorPentatonic => (2, 0),
+        Scale::Chromatic => (2, 1),
+        Scale::Custom(_) => (1, 1),
     };
-    match nx < 9 {
-        true => Some((nx, x / 16)),
+    grid.color(red, green)
+}
+
+/// Work out a BPM from a series of tap timestamps
+/// Averages the intervals between the last four taps
+/// Returns None until at least two taps are recorded
+fn tap_bpm(taps: &[Instant]) -> Option<f64> {
+    let recent = &taps[taps.len().saturating_sub(4)..];
+    if recent.len() < 2 {
+        return None;
+    }
+    let span = recent[recent.len() - 1].duration_since(recent[0]);
+    let avg = span.as_secs_f64() / (recent.len() - 1) as f64;
+    match avg > 0.0 {
+        true => Some(60.0 / avg),
         _ => None,
     }
 }
 
-/// Calculate the LED color on the Launchpad
-/// Launchpad only has two color options for LEDs, Red and Green,
-/// each with 3 levels of brightness
-fn led_color(red: u8, green: u8) -> u8 {
-    match (red, green) {
-        (0..=3, 0..=3) => 12 | red | (16 * green),
-        _ => 127,
+/// How late a lane's step plays for a swing ratio: every second
+/// step is pushed back so the first of each pair takes `ratio` of it
+fn swing_ticks(ratio: f64, step_ticks: usize, step: usize) -> usize {
+    match step % 2 {
+        1 => ((ratio - 0.5).max(0.0) * 2.0 * step_ticks as f64).round() as usize,
+        _ => 0,
     }
 }
 
+/// How many ticks of a span a note should sound for at a given gate
+/// Always at least one tick, and always released before the span ends
+fn gate_ticks(gate: f32, span: usize) -> usize {
+    let ticks = (gate.clamp(0.0, 1.0) * span as f32).round() as usize;
+    ticks.clamp(1, span.saturating_sub(1).max(1))
+}
+
 // Column state for the physical device
 // Stores it's value to indicate it's position
 // and it's MIDI note value to easily unset the previous LED
 // val: a value between 0 and 7
 // note: arbitrarily any value between 0-255, preferrably 0-127
+// vel: the velocity the column's note is played with
+// octave_offset: octaves added to the global octave for this column
+// prob: chance (0-100) that the column actually triggers
+// ratchet: how many times (1-4) the note repeats within the step
 #[derive(Debug, Copy, Clone)]
 pub struct ArpCol {
     pub val: u8,
     pub note: u8,
+    pub vel: u8,
+    pub octave_offset: i8,
+    pub prob: u8,
+    pub ratchet: u8,
 }
 
 impl ArpCol {
     fn new() -> ArpCol {
-        ArpCol { val: 0, note: 0 }
+        ArpCol {
+            val: 0,
+            note: 0,
+            vel: 100,
+            octave_offset: 0,
+            prob: 100,
+            ratchet: 1,
+        }
     }
 }
 
@@ -213,10 +422,10 @@ pub struct Tracker {
 }
 
 impl Tracker {
-    fn new() -> Tracker {
+    fn new(color: u8) -> Tracker {
         Tracker {
             index: 0,
-            btn: [NOTE, 112, 127, 0],
+            btn: [NOTE, 112, color, 0],
         }
     }
 
@@ -225,360 +434,3292 @@ impl Tracker {
         return (bmin <= self.index) && (self.index <= (bmin+7));
     }
 
-    fn update(&mut self) {
-        self.index += 1;
-        if self.index == 32 {
-            self.index = 0;
+    /// Move the tracker to the arpeggiator's current index
+    fn follow(&mut self, index: usize) {
+        self.index = index as u8;
+        self.btn[1] = 112 + (self.index % 8);
+    }
+}
+
+/// One pattern and where playback is in it. Each lane plays on its
+/// own channel at its own step rate (in scheduler ticks), and the
+/// grid edits whichever lane is selected.
+/// With a chain, the four views play as separate 8-step segments
+/// in the chain's order instead of as one long pattern.
+#[derive(Debug, Clone)]
+pub struct Lane {
+    pub buffer: [ArpCol; MAX_STEPS],
+    pub hold_buffer: [ArpCol; MAX_STEPS],
+    pub index: usize,
+    pub descending: bool,
+    pub pattern_len: usize,
+    pub octave: u8,
+    pub channel: u8,
+    pub step_ticks: usize,
+    pub jobs: Vec<JobId>,
+    pub clock_acc: usize,
+    pub active_notes: Vec<u8>,
+    pub accents: [bool; MAX_STEPS],
+    pub chain: Vec<u8>,
+    pub chain_pos: usize,
+    pub chain_step: usize,
+    pub step_count: usize,
+}
+
+impl Lane {
+    fn new(channel: u8, step_ticks: usize) -> Lane {
+        Lane {
+            buffer: [ArpCol::new(); MAX_STEPS],
+            hold_buffer: [ArpCol::new(); MAX_STEPS],
+            index: 0,
+            descending: false,
+            pattern_len: MAX_STEPS,
+            octave: 5,
+            channel: channel & 0x0F,
+            step_ticks: step_ticks.max(1),
+            jobs: Vec::new(),
+            clock_acc: 0,
+            active_notes: Vec::with_capacity(8),
+            accents: [false; MAX_STEPS],
+            chain: Vec::new(),
+            chain_pos: 0,
+            chain_step: 0,
+            step_count: 0,
+        }
+    }
+
+    /// The first column and length of the stretch playing now:
+    /// the chain's current segment, or the whole pattern
+    fn segment(&self) -> (usize, usize) {
+        match self.chain.get(self.chain_pos) {
+            Some(seg) => {
+                // a segment running into the end of the pattern is cut short
+                let base = *seg as usize * 8;
+                (base, 8.min(self.pattern_len.saturating_sub(base)).max(1))
+            }
+            None => (0, self.pattern_len.clamp(1, MAX_STEPS)),
+        }
+    }
+
+    /// Go back to the first column of the pattern, or of the
+    /// chain's first segment
+    fn rewind(&mut self) {
+        self.chain_pos = 0;
+        self.chain_step = 0;
+        self.step_count = 0;
+        self.index = self.segment().0;
+        self.descending = false;
+    }
+
+    /// The columns playback reads from: the latched snapshot
+    /// while holding, the live pattern otherwise
+    fn playing_buffer(&self, hold: bool) -> &[ArpCol; MAX_STEPS] {
+        match hold {
+            true => &self.hold_buffer,
+            _ => &self.buffer,
         }
     }
+}
+
+/// Everything needed to set up an arpeggiator: which devices to
+/// open and the musical settings it starts with
+#[derive(Debug, Clone)]
+pub struct ArpConfig {
+    pub grid_name: String,
+    pub out_name: String,
+    pub model: LaunchpadModel,
+    pub out_channel: u8,
+    pub bpm: u16,
+    pub octave: u8,
+    pub scale: Scale,
+    pub pattern_len: usize,
+    #[cfg(feature = "osc")]
+    pub osc_out: Option<String>,
+    #[cfg(feature = "osc")]
+    pub osc_in: Option<String>,
+}
 
-    fn move_right(&mut self) {
-        self.btn[1] += 1;
-        if self.btn[1] == 120 {
-            self.btn[1] = 112;
+impl Default for ArpConfig {
+    fn default() -> ArpConfig {
+        ArpConfig {
+            grid_name: "Launchpad MIDI 1".into(),
+            out_name: "Midi Through Port-0".into(),
+            model: LaunchpadModel::Mk1,
+            out_channel: 0,
+            bpm: 120,
+            octave: 5,
+            scale: Scale::Major,
+            pattern_len: MAX_STEPS,
+            #[cfg(feature = "osc")]
+            osc_out: None,
+            #[cfg(feature = "osc")]
+            osc_in: None,
         }
     }
 }
 
+/// Where the arp's devices came from, so they can be reopened by
+/// name if one drops out mid-session
+pub struct Ports<'a> {
+    pub ctx: &'a pm::PortMidi,
+    pub config: ArpConfig,
+}
+
+/// Open the output port and the grid named in a config
+fn open_devices<'a>(config: &ArpConfig, ctx: &'a pm::PortMidi) -> Result<(Device<'a>, Launchpad<'a>), String> {
+    let dev = Device::new(&config.out_name, ctx).map_err(|e| e.to_string())?;
+    let lp = Device::new(&config.grid_name, ctx).map_err(|e| e.to_string())?;
+    let mut lp = Launchpad::new(lp, config.model);
+    lp.enter_programmer_mode().map_err(|e| e.to_string())?;
+    Ok((dev, lp))
+}
+
 /// Arpeggiator struct layout
 /// Requires a lifetime for Portmidi device connections
 pub struct Arp<'a> {
-    pub midi_out: Device<'a>,
-    pub grid_io: Device<'a>,
+    pub midi_out: Box<dyn MidiIo + 'a>,
+    pub grid_io: Box<dyn Grid + 'a>,
     pub running: bool,
     pub playing: bool,
     pub scheduler: Scheduler<Msg>,
-    pub index: usize,
+    pub lanes: Vec<Lane>,
+    pub lane: usize,
+    pub direction: Direction,
+    pub rng: Rng,
     pub buffer_index: u8,
-    pub buffer: [ArpCol; 32],
+    pub hold: bool,
+    pub recording: bool,
     pub buffer_btn: BtnArr,
     pub pp_btn: BtnArr,
     pub scale: Scale,
     pub scale_btn: BtnArr,
-    pub octave: u8,
+    pub shift_held: bool,
+    pub shift_used: bool,
+    pub root: u8,
     pub octave_btn: BtnArr,
-    pub bpm: u8,
+    pub side_mode: SideMode,
+    pub row_muted: [bool; 8],
+    pub row_solo: [bool; 8],
+    pub cc_num: u8,
+    pub pressure_cc: Option<u8>,
+    pub max_voices: usize,
+    pub cc_level: u8,
+    pub bend_row: u8,
+    pub program: Option<u8>,
+    pub metronome: bool,
+    pub click_beat: usize,
+    pub click_channel: u8,
+    pub click_note: u8,
+    pub bpm: u16,
+    pub taps: Vec<Instant>,
+    pub clock_out: bool,
+    pub clock_phase: usize,
+    pub clock_in: bool,
+    pub last_pulse: Option<Instant>,
+    pub gate_min: f32,
+    pub gate_max: f32,
+    pub chord: bool,
+    pub humanize: u8,
+    pub show_position: bool,
+    pub stutter: Option<usize>,
+    pub transpose: i8,
     pub tracker: Tracker,
+    pub parser: MidiParser,
+    pub leds: [u8; FRAME_LEN],
+    pub led_state: [u8; FRAME_LEN],
+    pub ports: Option<Ports<'a>>,
+    pub vel_curve: VelocityCurve,
+    pub swing_idx: usize,
+    pub input_buf: Vec<pm::MidiEvent>,
+    pub grid_events: Vec<GridEvent>,
+    #[cfg(feature = "osc")]
+    pub osc_out: Option<OscSender>,
+    #[cfg(feature = "osc")]
+    pub osc_in: Option<OscListener>,
 }
 
 impl Arp<'_> {
-    fn new<'a>(midi_out: Device<'a>, grid_io: Device<'a>) -> Arp<'a> {
-        let buffer_btn = [MIDI, 104, 127, 0];
-        let pp_btn = [MIDI, 108, led_color(3, 0), 0];
-        let scale_btn = [MIDI, 110, led_color(1, 3), 0];
-        let octave_btn = [NOTE, 72, 127, 0];
+    fn new<'a>(midi_out: Box<dyn MidiIo + 'a>, grid_io: Box<dyn Grid + 'a>) -> Arp<'a> {
+        let full = grid_io.color(3, 3);
+        let buffer_btn = [MIDI, 104, full, 0];
+        let pp_btn = [MIDI, 108, grid_io.color(3, 0), 0];
+        let scale_btn = [MIDI, 110, scale_led(&Scale::Major, grid_io.as_ref()), 0];
+        let octave_btn = [NOTE, 72, full, 0];
         Arp {
             midi_out: midi_out,
             grid_io: grid_io,
             running: true,
             playing: false,
             scheduler: Scheduler::new(),
-            index: 0,
+            lanes: vec![Lane::new(0, Subdivision::Eighth.ticks())],
+            lane: 0,
+            direction: Direction::Up,
+            rng: Rng::from_time(),
             buffer_index: 0,
-            buffer: [ArpCol::new(); 32],
+            hold: false,
+            recording: false,
             buffer_btn: buffer_btn,
             pp_btn: pp_btn,
             scale: Scale::Major,
             scale_btn: scale_btn,
-            octave: 5,
+            shift_held: false,
+            shift_used: false,
+            root: 0,
             octave_btn: octave_btn,
+            side_mode: SideMode::Octave,
+            row_muted: [false; 8],
+            row_solo: [false; 8],
+            cc_num: 1,
+            pressure_cc: None,
+            max_voices: 0,
+            cc_level: 0,
+            bend_row: BEND_CENTER,
+            program: None,
+            metronome: false,
+            click_beat: 0,
+            click_channel: 9,
+            click_note: 37,
             bpm: 120,
-            tracker: Tracker::new(),
+            taps: Vec::with_capacity(4),
+            clock_out: false,
+            clock_phase: 0,
+            clock_in: false,
+            last_pulse: None,
+            gate_min: 1.0,
+            gate_max: 1.0,
+            chord: false,
+            humanize: 0,
+            show_position: false,
+            stutter: None,
+            transpose: 0,
+            tracker: Tracker::new(full),
+            parser: MidiParser::new(),
+            leds: [0; FRAME_LEN],
+            led_state: [0; FRAME_LEN],
+            ports: None,
+            vel_curve: VelocityCurve::Linear,
+            swing_idx: 0,
+            input_buf: Vec::with_capacity(DEFAULT_BUFFER_SIZE),
+            grid_events: Vec::with_capacity(DEFAULT_BUFFER_SIZE),
+            #[cfg(feature = "osc")]
+            osc_out: None,
+            #[cfg(feature = "osc")]
+            osc_in: None,
+        }
+    }
+
+    /// Open the devices named in a config and build an arp from it
+    fn from_config(config: ArpConfig, ctx: &pm::PortMidi) -> Result<Arp<'_>, String> {
+        let (dev, mut lp) = open_devices(&config, ctx)?;
+        lp.scroll_text("lparp", lp.color(0, 3), 7)
+            .map_err(|e| format!("Failed to write to the grid: {}", e))?;
+
+        let mut arp = Arp::with_config(Box::new(dev), Box::new(lp), &config)?;
+        arp.ports = Some(Ports { ctx, config });
+        Ok(arp)
+    }
+
+    /// Build an arp on devices that are already open, set up
+    /// with the musical settings of a config
+    fn with_config<'a>(
+        midi_out: Box<dyn MidiIo + 'a>,
+        grid_io: Box<dyn Grid + 'a>,
+        config: &ArpConfig,
+    ) -> Result<Arp<'a>, String> {
+        let mut arp = Arp::new(midi_out, grid_io);
+        arp.set_out_channel(config.out_channel)?;
+        arp.bpm = config.bpm.clamp(MIN_BPM, MAX_BPM);
+        arp.lanes[0].octave = config.octave.min(7);
+        arp.octave_btn[1] = ((7 - arp.lanes[0].octave) * 16) + 8;
+        arp.scale_btn[2] = scale_led(&config.scale, arp.grid_io.as_ref());
+        arp.scale = config.scale.clone();
+        arp.lanes[0].pattern_len = config.pattern_len.clamp(1, MAX_STEPS);
+        #[cfg(feature = "osc")]
+        if let Some(target) = &config.osc_out {
+            let sender = OscSender::new(target.as_str())
+                .map_err(|e| format!("Failed to open OSC output {}: {}", target, e))?;
+            arp.osc_out = Some(sender);
+        }
+        #[cfg(feature = "osc")]
+        if let Some(addr) = &config.osc_in {
+            let listener = OscListener::bind(addr.as_str())
+                .map_err(|e| format!("Failed to listen for OSC on {}: {}", addr, e))?;
+            arp.osc_in = Some(listener);
+        }
+        Ok(arp)
+    }
+
+    /// The lane the grid is editing
+    fn cur(&self) -> &Lane {
+        &self.lanes[self.lane]
+    }
+
+    fn cur_mut(&mut self) -> &mut Lane {
+        &mut self.lanes[self.lane]
+    }
+
+    /// Add another lane playing on `channel` every `step_ticks`
+    /// scheduler ticks. Call before `schedule_lanes()`.
+    fn add_lane(&mut self, channel: u8, step_ticks: usize, octave: u8) -> Result<(), String> {
+        if channel > 15 {
+            return Err(format!("Invalid MIDI channel {}, expected 0-15", channel));
         }
+        let mut lane = Lane::new(channel, step_ticks);
+        lane.octave = octave.min(7);
+        self.lanes.push(lane);
+        Ok(())
+    }
+
+    /// Give every lane its step and note jobs on the scheduler
+    fn schedule_lanes(&mut self) {
+        for i in 0..self.lanes.len() {
+            self.schedule_lane(i);
+        }
+    }
+
+    /// (Re)place a lane's step and note jobs at its step length,
+    /// lined up with the scheduler's first tick so every lane
+    /// whose steps divide a beat still lands on the beat
+    fn schedule_lane(&mut self, i: usize) {
+        for id in std::mem::take(&mut self.lanes[i].jobs) {
+            self.scheduler.remove(id);
+        }
+        let step = self.lanes[i].step_ticks;
+        let offset = step - self.scheduler.current_tick() % step;
+        self.lanes[i].jobs = vec![
+            self.scheduler.interval_with_offset(step, offset, Msg::UpdateState(i)),
+            self.scheduler.interval_with_offset(step, offset, Msg::FlushNotes(i)),
+        ];
+    }
+
+    /// Step the selected lane through quarters, eighths, eighth
+    /// triplets and sixteenths. Lanes at any other step length
+    /// start over from quarters.
+    fn cycle_subdivision(&mut self) -> MidiRes {
+        let next = match Subdivision::from_ticks(self.cur().step_ticks) {
+            Some(sub) => sub.next(),
+            None => Subdivision::Quarter,
+        };
+        self.cur_mut().step_ticks = next.ticks();
+        self.cur_mut().clock_acc = 0;
+        self.schedule_lane(self.lane);
+        Ok(())
+    }
+
+    /// Hand the grid to the next lane and redraw it
+    fn next_lane(&mut self) -> MidiRes {
+        self.lane = (self.lane + 1) % self.lanes.len();
+        self.octave_btn[1] = ((7 - self.cur().octave) * 16) + 8;
+        if (self.buffer_index as usize * 8) >= self.cur().pattern_len {
+            self.buffer_index = 0;
+            self.buffer_btn[1] = 104;
+        }
+        self.tracker.follow(self.cur().index);
+        self.render_ui()
+    }
+
+    /// Try to reopen both devices by name after an I/O error,
+    /// retrying until `RECONNECT_TIMEOUT` runs out. The pattern and
+    /// every setting are kept, and the whole UI is redrawn once
+    /// the grid is back. Gives the original error back on failure.
+    /// PortMidi only lists ports when its context is created, so this
+    /// rides out a port that errors for a moment, but a device that
+    /// was unplugged and plugged back in won't be found again.
+    fn reconnect(&mut self, err: MidiError) -> MidiRes {
+        let (ctx, config) = match &self.ports {
+            Some(ports) => (ports.ctx, ports.config.clone()),
+            None => return Err(err),
+        };
+        println!("Lost a device ({}), trying to reconnect", err);
+
+        // let go of the old ports before opening them again
+        self.midi_out = Box::new(MockDevice::new());
+        self.grid_io = Box::new(MockGrid::new());
+
+        let started = Instant::now();
+        while started.elapsed() < RECONNECT_TIMEOUT {
+            if let Ok((dev, lp)) = open_devices(&config, ctx) {
+                self.midi_out = Box::new(dev);
+                self.grid_io = Box::new(lp);
+                self.grid_io.flush_input()?;
+                self.redraw()?;
+                // the time spent reconnecting isn't owed as a burst of ticks
+                self.scheduler.resync();
+                println!("Reconnected");
+                return Ok(());
+            }
+            std::thread::sleep(RECONNECT_RETRY);
+        }
+        Err(err)
     }
 
     /// Sets running to `false` to shut the app loop off
     fn quit(&mut self) -> MidiRes {
         println!("Quitting program");
         self.running = false;
-        Ok(())
+        self.all_off()
     }
 
-    /// Checks if the device has any inputs
-    /// A list of events is scanned from the serial device
-    /// and fed in, with each message corresponding to an event
-    /// on the MIDI bus. For this device, there are two corresponding
-    /// status messages.
-    /// 176 => MIDI general message (pd -> midiin)
-    /// 144 => MIDI note message (pd -> notein)
-    /// Functionally we only care about an event when velocity=127
+    /// Checks if the grid has any inputs
+    /// Every button event since the last check is fed in:
+    /// top row buttons, side column buttons and grid pads.
+    /// Only the top row cares about releases.
     fn check_inputs(&mut self) -> MidiRes {
-        if let Ok(Some(evts)) = self.grid_io.input.read_n(1024) {
-            for e in evts {
-                let status = e.message.status;
-                let note = e.message.data1;
-                let vel = e.message.data2;
+        self.check_midi_inputs()?;
+        #[cfg(feature = "osc")]
+        self.check_osc_inputs()?;
+        // lent out and put back like the MIDI input buffer
+        let mut events = std::mem::take(&mut self.grid_events);
+        let res = match self.grid_io.poll(&mut events) {
+            Ok(_) => events.iter().try_for_each(|e| self.grid_input(*e)),
+            Err(e) => Err(e),
+        };
+        self.grid_events = events;
+        res
+    }
 
-                if vel == 0 {
-                    return Ok(());
-                }
-                match status {
-                    MIDI => self.top_row_dispatch(note)?,
-                    NOTE => self.grid_button_dispatch(note)?,
-                    _ => {}
-                }
+    /// Handle one button event from the grid
+    fn grid_input(&mut self, e: GridEvent) -> MidiRes {
+        match e {
+            GridEvent::Top { idx, pressed: true } => self.top_row_dispatch(idx),
+            GridEvent::Top { idx, .. } => self.top_row_release(idx),
+            GridEvent::Side { y, pressed: true } => self.side_button_dispatch(y),
+            GridEvent::Pad { x, y, pressed: true } => self.grid_button_dispatch(x, y),
+            GridEvent::Pressure { value } => self.send_pressure(value),
+            // grid releases carry no action, but the rest of
+            // the batch still has to be handled
+            _ => Ok(()),
+        }
+    }
+
+    /// Checks the MIDI output device's input side for real-time
+    /// messages from an external clock source, e.g. a drum machine,
+    /// as well as transport start/continue/stop from a DAW and
+    /// aftertouch to pass on as a control change
+    fn check_midi_inputs(&mut self) -> MidiRes {
+        if !self.midi_out.has_input() {
+            return Ok(());
+        }
+        // the buffer is lent out while the events are handled, then
+        // put back so its allocation is reused next time
+        let mut evts = std::mem::take(&mut self.input_buf);
+        let res = match self.midi_out.read_into(&mut evts, DEFAULT_BUFFER_SIZE) {
+            Ok(_) => evts.iter().try_for_each(|e| self.midi_input(*e)),
+            Err(e) => Err(e),
+        };
+        self.input_buf = evts;
+        res
+    }
+
+    /// Handle one event from the MIDI output device's input side
+    fn midi_input(&mut self, e: pm::MidiEvent) -> MidiRes {
+        match self.parser.parse(e) {
+            Some(MidiMessage::Clock) => self.clock_pulse(),
+            Some(MidiMessage::Start) => self.restart(),
+            Some(MidiMessage::Continue) => self.play(),
+            Some(MidiMessage::Stop) => self.pause(),
+            Some(MidiMessage::NoteOn { note, .. }) if self.recording => self.record_note(note),
+            Some(MidiMessage::ChannelPressure { value, .. })
+            | Some(MidiMessage::PolyAftertouch { value, .. }) => self.send_pressure(value),
+            _ => Ok(()),
+        }
+    }
+
+    /// Apply remote control messages queued by the OSC listener:
+    /// `/play`, `/pause`, `/bpm <n>` and `/scale <n>` (the nth scale
+    /// in the cycle, 0 = major, wrapping past the last)
+    #[cfg(feature = "osc")]
+    fn check_osc_inputs(&mut self) -> MidiRes {
+        let msgs = match &self.osc_in {
+            Some(osc) => osc.poll(),
+            None => return Ok(()),
+        };
+        for (addr, args) in msgs {
+            let num = match args.first() {
+                Some(OscArg::Int(i)) => Some(*i as f64),
+                Some(OscArg::Float(f)) => Some(*f as f64),
+                _ => None,
+            };
+            match (addr.as_str(), num) {
+                ("/play", _) => self.play()?,
+                ("/pause", _) => self.pause()?,
+                // NaN and infinity are dropped rather than saturated
+                (_, Some(n)) if !n.is_finite() => {}
+                ("/bpm", Some(bpm)) => self.set_bpm(bpm.round().clamp(0.0, MAX_BPM as f64) as u16)?,
+                ("/scale", Some(n)) if n >= 0.0 => self.select_scale((n % SCALE_COUNT as f64) as usize)?,
+                _ => {}
             }
         }
         Ok(())
     }
 
-    /// Dispatch logic for top-row MIDI messages
-    fn top_row_dispatch(&mut self, note: MidiVal) -> MidiRes {
-        if note < 104 {
+    /// Handle one incoming clock pulse. When slaved to an external
+    /// clock, the pattern steps forward once enough pulses for a
+    /// step have arrived (24 pulses per quarter note).
+    fn clock_pulse(&mut self) -> MidiRes {
+        if !self.clock_in {
             return Ok(());
         }
-        let idx = note - 104;
+        self.last_pulse = Some(Instant::now());
+        for i in 0..self.lanes.len() {
+            let lane = &mut self.lanes[i];
+            lane.clock_acc += TICKS_PER_BEAT as usize;
+            let step = lane.step_ticks * CLOCK_PPQN;
+            if lane.clock_acc >= step {
+                lane.clock_acc -= step;
+                if self.playing {
+                    self.update_state(i)?;
+                    self.flush_notes(i)?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// True while an external clock is driving playback
+    /// Falls back to internal timing once pulses stop arriving
+    fn external_clock(&self) -> bool {
+        match (self.clock_in, self.last_pulse) {
+            (true, Some(t)) => t.elapsed() < CLOCK_TIMEOUT,
+            _ => false,
+        }
+    }
+
+    /// Dispatch logic for top-row button presses
+    fn top_row_dispatch(&mut self, idx: u8) -> MidiRes {
+        if self.shift_held && idx != 6 {
+            return self.top_row_function(idx);
+        }
         match idx {
             0..=3 => {
                 // if the target buffer is different than current,
                 // reflash the entire UI and change the buffer index
                 // mutate the buffer highlighted button as well
+                // views past the end of the pattern are unused
+                if (idx as usize * 8) >= self.cur().pattern_len {
+                    return Ok(());
+                }
                 if idx != self.buffer_index {
                     self.buffer_index = idx;
-                    self.buffer_btn[1] = note;
+                    self.buffer_btn[1] = 104 + idx;
                     self.render_ui()
                 } else {
                     Ok(())
                 }
             }
             4 => self.pause(),
+            // holding play while already playing stutters the
+            // selected lane's step
+            5 if self.playing => {
+                self.stutter = Some(self.lane);
+                Ok(())
+            }
             5 => self.play(),
-            6 => self.invert_scale(),
+            6 => {
+                // the scale button doubles as a shift key, so wait
+                // for the release to know whether it was just a tap
+                self.shift_held = true;
+                self.shift_used = false;
+                self.render_top_row()?;
+                self.render_side()
+            }
             7 => self.quit(),
             _ => { Ok(()) }
         }
     }
 
-    /// Dispatch for grid-based MIDI messages
-    fn grid_button_dispatch(&mut self, note: MidiVal) -> MidiRes {
-        if let Some((x, y)) = find_lp_xy(note) {
-            if x == 8 {
-                self.grid_io.output.write_message([
-                    NOTE, self.octave_btn[1], 0, 0
-                ])?;
-                self.octave = 7 - y;
-                self.octave_btn[1] = note;
-                self.grid_io.output.write_message(self.octave_btn)?;
-                return Ok(());
+    /// Dispatch logic for top-row button releases
+    fn top_row_release(&mut self, idx: u8) -> MidiRes {
+        if idx == 5 {
+            self.stutter = None;
+        }
+        if idx == 6 && self.shift_held {
+            self.shift_held = false;
+            self.render_top_row()?;
+            self.render_side()?;
+            if !self.shift_used {
+                return self.cycle_scale();
             }
-            let offset = ((self.buffer_index*8) + x) as usize;
-            let new_val = 7 - y; // inverting the value
-
-            // grab a reference to the column
-            let column = &mut self.buffer[offset];
-            if column.val != new_val {
-                // turn off old LED if there was a non-zero value
-                if column.val != 0 {
-                    self.grid_io.output.write_message([
-                        NOTE, column.note, 0, 0
-                    ])?;
-                }
+        }
+        Ok(())
+    }
 
-                // and turning on the new LED
-                if new_val != 0 {
-                    self.grid_io.output.write_message([
-                        NOTE, note, 127, 0
-                    ])?;
-                }
-                column.val = new_val;
-                column.note = note;
+    /// Draw the top row, which shows the view/play buttons normally
+    /// and the state of the secondary functions while shift
+    /// is held
+    fn render_top_row(&mut self) -> MidiRes {
+        for note in [104, 105, 106, 107, 108, 109, 111] {
+            self.draw([MIDI, note, 0, 0])?;
+        }
+        if self.shift_held {
+            if self.hold {
+                self.draw([MIDI, 104, self.grid_io.color(0, 3), 0])?;
+            }
+            if self.recording {
+                self.draw([MIDI, 105, self.grid_io.color(3, 0), 0])?;
+            }
+            // tempo nudge buttons go dark at the ends of the range
+            if self.bpm > MIN_BPM {
+                self.draw([MIDI, 108, self.grid_io.color(1, 1), 0])?;
+            }
+            if self.bpm < MAX_BPM {
+                self.draw([MIDI, 109, self.grid_io.color(1, 1), 0])?;
             }
+            self.draw([MIDI, 111, self.grid_io.color(3, 0), 0])
+        } else {
+            self.draw(self.buffer_btn)?;
+            self.draw(self.pp_btn)
         }
-        Ok(())
     }
 
-    /// Activate the playing mode and toggle the playing LED
-    /// while also deactivating the paused LED
-    fn play(&mut self) -> MidiRes {
-        if !self.playing {
-            self.playing = true;
-            self.grid_io.write(176, 108, 0, 0);
-            self.pp_btn[1] = 109;
-            self.pp_btn[2] = led_color(0, 3);
-            self.grid_io.output.write_message(self.pp_btn)?;
+    /// Secondary functions for the top row,
+    /// reached by holding shift
+    fn top_row_function(&mut self, idx: u8) -> MidiRes {
+        self.shift_used = true;
+        match idx {
+            0 => self.toggle_hold(),
+            1 => self.toggle_recording(),
+            2 => self.cycle_direction(),
+            3 => self.tap_tempo(),
+            4 => self.set_bpm(self.bpm.saturating_sub(1)),
+            5 => self.set_bpm(self.bpm + 1),
+            7 => self.panic(),
+            _ => Ok(()),
         }
-        Ok(())
     }
 
-    /// Inverse action of .play()
-    fn pause(&mut self) -> MidiRes {
-        if self.playing {
-            self.playing = false;
-            self.grid_io.write(176, 109, 0, 0);
-            self.pp_btn[1] = 108;
-            self.pp_btn[2] = led_color(3, 0);
-            self.grid_io.output.write_message(self.pp_btn)?;
+    /// Secondary functions for the right-most column,
+    /// reached by holding shift
+    fn side_function(&mut self, y: u8) -> MidiRes {
+        self.shift_used = true;
+        match y {
+            0 => self.set_root(self.root + 1),
+            1 => self.set_root(self.root + 11),
+            2 => self.transpose = self.transpose.saturating_add(1).min(MAX_TRANSPOSE),
+            3 => self.transpose = self.transpose.saturating_sub(1).max(-MAX_TRANSPOSE),
+            4 => self.metronome = !self.metronome,
+            5 => self.cycle_swing(),
+            6 => return self.next_lane(),
+            7 => return self.cycle_side_mode(),
+            _ => {}
         }
-        Ok(())
+        self.render_side()
     }
 
-    /// Invert the current scale and change the active LED to reflect it
-    fn invert_scale(&mut self) -> MidiRes {
-        match self.scale {
-            Scale::Major => {
-                self.scale = Scale::Minor;
-                self.scale_btn[2] = led_color(3, 1);
-            },
-            _ => {
-                self.scale = Scale::Major;
-                self.scale_btn[2] = led_color(1, 3);
-            }
+    /// Step to the next swing preset, wrapping back to straight
+    fn cycle_swing(&mut self) {
+        self.swing_idx = (self.swing_idx + 1) % SWING_PRESETS.len();
+    }
+
+    /// Side column while shift is held: the secondary
+    /// functions, with the metronome and swing showing their state
+    /// (swing runs from green for straight to red for triplets)
+    fn render_side_functions(&mut self) -> MidiRes {
+        let dim = self.grid_io.color(1, 1);
+        for y in [0, 1, 2, 3, 7] {
+            self.draw([NOTE, (y * 16) + 8, dim, 0])?;
         }
-        self.grid_io.output.write_message(self.scale_btn)
+        if self.lanes.len() > 1 {
+            self.draw([NOTE, (6 * 16) + 8, self.grid_io.color(3, 3), 0])?;
+        }
+        let click = match self.metronome {
+            true => self.grid_io.color(0, 3),
+            _ => dim,
+        };
+        self.draw([NOTE, (4 * 16) + 8, click, 0])?;
+        let swing = match self.swing_idx {
+            0 => self.grid_io.color(0, 3),
+            1 => self.grid_io.color(1, 3),
+            2 => self.grid_io.color(3, 3),
+            3 => self.grid_io.color(3, 1),
+            _ => self.grid_io.color(3, 0),
+        };
+        self.draw([NOTE, (5 * 16) + 8, swing, 0])
     }
 
-    /// Update all components that rely on a note tick
-    fn update_state(&mut self) -> MidiRes {
-        // bump the note index counter
-        if self.playing {
-            self.index += 1;
-            if self.index == 32 {
-                self.index = 0;
+    /// One-shot pattern operations for the side column in Edit mode
+    fn edit_function(&mut self, y: u8) -> MidiRes {
+        match y {
+            0 => self.randomize(50),
+            1 => self.reverse(),
+            2 => self.invert_values(),
+            3 => self.rotate(-1),
+            4 => self.rotate(1),
+            5 => {
+                let views = self.cur().pattern_len.div_ceil(8) as u8;
+                self.copy_view(self.buffer_index, (self.buffer_index + 1) % views)
             }
+            6 => self.cycle_subdivision(),
+            _ => Ok(()),
         }
+    }
 
-        // turn off the tracker's previous LED
-        // do this before we "move" the button
-        self.grid_io.output.write_message([
-            NOTE, self.tracker.btn[1], 0, 0
-        ])?;
-        
-        self.tracker.update();
-        self.tracker.move_right();
-        
-        // turn on the tracker's LED if it's "on screen"
-        if self.tracker.in_range(self.buffer_index) {
-            self.grid_io.output.write_message(
-                self.tracker.btn
-            )?;
+    /// Change how many steps the selected lane plays, going back to
+    /// the first view if the one shown is now past the end
+    /// Chained views that now start past the end are dropped, and
+    /// the lane goes back to the top of what's left of its chain
+    fn set_pattern_len(&mut self, len: usize) -> MidiRes {
+        let lane = self.cur_mut();
+        lane.pattern_len = len.clamp(1, MAX_STEPS);
+        let (chained, end) = (lane.chain.len(), lane.pattern_len);
+        lane.chain.retain(|seg| (*seg as usize * 8) < end);
+        if lane.chain.len() != chained {
+            lane.rewind();
         }
+        if (self.buffer_index as usize * 8) >= self.cur().pattern_len {
+            self.buffer_index = 0;
+            self.buffer_btn[1] = 104;
+        }
+        self.render_ui()
+    }
 
-        Ok(())
+    /// Switch what the side column does and redraw it
+    /// Leaving Bend mode lets go of the bend, back to the centre
+    fn cycle_side_mode(&mut self) -> MidiRes {
+        if self.side_mode == SideMode::Bend && self.bend_row != BEND_CENTER {
+            self.send_bend(BEND_CENTER)?;
+        }
+        self.side_mode = self.side_mode.next();
+        self.render_side()
     }
 
-    /// Send note messages from the current state index
-    /// Only send messages if a column is active
-    fn flush_notes(&mut self) -> MidiRes {
-        let col = &self.buffer[self.index];
-        if col.val > 0 {
-            if let Some(base_note) = calc_note(col.val, &self.scale) {
-                self.midi_out.output.write_message([
-                    NOTE, base_note+(self.octave*12), 127, 1
-                ])?;
+    /// Draw the side column for the current side mode
+    fn render_side(&mut self) -> MidiRes {
+        for y in 0..8 {
+            self.draw([NOTE, (y * 16) + 8, 0, 0])?;
+        }
+        if self.shift_held {
+            return self.render_side_functions();
+        }
+        match self.side_mode {
+            SideMode::Octave => self.draw(self.octave_btn),
+            SideMode::Edit => {
+                for y in 0..EDIT_ACTIONS {
+                    self.draw([NOTE, (y * 16) + 8, self.grid_io.color(1, 1), 0])?;
+                }
+                Ok(())
+            }
+            SideMode::Mute => {
+                for y in 0..8 {
+                    let color = match self.row_muted[y as usize] {
+                        true => self.grid_io.color(3, 0),
+                        _ => self.grid_io.color(0, 1),
+                    };
+                    self.draw([NOTE, (y * 16) + 8, color, 0])?;
+                }
+                Ok(())
+            }
+            SideMode::Solo => {
+                for y in 0..8 {
+                    let color = match self.row_solo[y as usize] {
+                        true => self.grid_io.color(3, 3),
+                        _ => self.grid_io.color(1, 0),
+                    };
+                    self.draw([NOTE, (y * 16) + 8, color, 0])?;
+                }
+                Ok(())
+            }
+            SideMode::Fader => {
+                // light a bar from the bottom up to the last value sent
+                for y in 0..8 {
+                    let color = match 7 - y <= self.cc_level {
+                        true => self.grid_io.color(0, 3),
+                        _ => 0,
+                    };
+                    self.draw([NOTE, (y * 16) + 8, color, 0])?;
+                }
+                Ok(())
+            }
+            SideMode::Bend => {
+                for y in 0..8 {
+                    let color = match (y == self.bend_row, y == BEND_CENTER) {
+                        (true, _) => self.grid_io.color(3, 3),
+                        (_, true) => self.grid_io.color(1, 1),
+                        _ => 0,
+                    };
+                    self.draw([NOTE, (y * 16) + 8, color, 0])?;
+                }
+                Ok(())
+            }
+            SideMode::Tempo => {
+                let level = Self::tempo_level(self.bpm);
+                for y in 0..8 {
+                    let color = match 8 - y <= level {
+                        true => self.grid_io.color(3, 1),
+                        _ => 0,
+                    };
+                    self.draw([NOTE, (y * 16) + 8, color, 0])?;
+                }
+                Ok(())
+            }
+            SideMode::Length => {
+                let level = self.cur().pattern_len.div_ceil(4) as u8;
+                for y in 0..8 {
+                    let color = match 8 - y <= level {
+                        true => self.grid_io.color(0, 3),
+                        _ => 0,
+                    };
+                    self.draw([NOTE, (y * 16) + 8, color, 0])?;
+                }
+                Ok(())
+            }
+            SideMode::Program => {
+                for y in 0..8 {
+                    let color = match self.program == Some(y) {
+                        true => self.grid_io.color(3, 3),
+                        _ => self.grid_io.color(1, 1),
+                    };
+                    self.draw([NOTE, (y * 16) + 8, color, 0])?;
+                }
+                Ok(())
             }
         }
-        Ok(())
     }
 
-    /// Clears the board of all LED values
-    fn clear_board(&mut self) -> MidiRes {
-        self.grid_io.output.write_message([MIDI, 0, 0, 0])
+    /// How many side LEDs (1-8) light up for a tempo, spreading
+    /// the whole BPM range evenly over the column
+    fn tempo_level(bpm: u16) -> u8 {
+        let bpm = bpm.clamp(MIN_BPM, MAX_BPM) - MIN_BPM;
+        (1 + (bpm as u32 * 8) / (MAX_BPM - MIN_BPM + 1) as u32) as u8
     }
 
-    /// Main function to re-draw every element onto the device.
-    /// Clears the full thing and sends out all UI LED messages.
-    fn render_ui(&mut self) -> MidiRes {
-        // clear board for a full wipe
-        self.clear_board()?;
+    /// The 14-bit pitch-bend value for a side column row. The centre
+    /// row is no bend, rows above it bend up to the top of the range
+    /// and rows below bend down to the bottom.
+    fn bend_value(y: u8) -> u16 {
+        let y = y.min(7) as u32;
+        let center = BEND_CENTER as u32;
+        let value = match y <= center {
+            true => 8192 + ((center - y) * 8191) / center,
+            _ => 8192 - ((y - center) * 8192) / (7 - center),
+        };
+        value as u16
+    }
 
-        // draw UI elements
-        self.grid_io.output.write_message(self.buffer_btn)?;
-        self.grid_io.output.write_message(self.pp_btn)?;
-        self.grid_io.output.write_message(self.scale_btn)?;
-        self.grid_io.output.write_message(self.octave_btn)?;
+    /// Send a pitch bend from a side column row
+    fn send_bend(&mut self, y: u8) -> MidiRes {
+        self.bend_row = y.min(7);
+        let msg = MidiMessage::PitchBend {
+            channel: self.cur().channel,
+            value: Self::bend_value(y),
+        };
+        self.midi_out.write_message(msg.to_bytes())
+    }
 
-        // draw tracker if it's on screen
-        // note: this part works
-        if self.tracker.in_range(self.buffer_index) {
-            self.grid_io.output.write_message(self.tracker.btn)?;
+    /// Scale a column value (0-7) linearly onto a CC value (0-127)
+    fn cc_value(val: u8) -> u8 {
+        ((val.min(7) as u16 * 127) / 7) as u8
+    }
+
+    /// Send a column value as a control change on the configured
+    /// controller, e.g. the mod wheel or a filter cutoff
+    fn send_cc(&mut self, val: u8) -> MidiRes {
+        self.cc_level = val.min(7);
+        let value = Self::cc_value(val);
+        let channel = self.cur().channel;
+        self.midi_out.write_message([MIDI | channel, self.cc_num, value, 0])
+    }
+
+    /// Pass pad pressure (aftertouch) on as a control change,
+    /// if a controller was picked for it
+    fn send_pressure(&mut self, value: u8) -> MidiRes {
+        match self.pressure_cc {
+            Some(cc) => {
+                let channel = self.cur().channel;
+                self.midi_out.write_message([MIDI | channel, cc, value.min(127), 0])
+            }
+            None => Ok(()),
         }
-        
-        // render all cells
-        for c in 0..8 {
-            let index = ((self.buffer_index*8) + c) as usize;
-            let col = &self.buffer[index];
-            if col.val > 0 {
-                self.grid_io.output.write_message([0x90, col.note, 127, 0])?;
+    }
+
+    /// Dispatch for the right-most (side) column
+    fn side_button_dispatch(&mut self, y: u8) -> MidiRes {
+        if self.shift_held {
+            return self.side_function(y);
+        }
+        match self.side_mode {
+            SideMode::Edit => return self.edit_function(y),
+            SideMode::Mute => {
+                self.row_muted[y as usize] = !self.row_muted[y as usize];
+                return self.render_side();
+            }
+            SideMode::Solo => {
+                self.row_solo[y as usize] = !self.row_solo[y as usize];
+                return self.render_side();
             }
+            SideMode::Fader => {
+                self.send_cc(7 - y)?;
+                return self.render_side();
+            }
+            SideMode::Bend => {
+                self.send_bend(y)?;
+                return self.render_side();
+            }
+            SideMode::Tempo => return Ok(()),
+            SideMode::Length => return self.set_pattern_len((8 - y as usize) * 4),
+            SideMode::Program => {
+                let channel = self.cur().channel;
+                self.midi_out.program_change(channel, y)?;
+                self.program = Some(y);
+                return self.render_side();
+            }
+            SideMode::Octave => {}
         }
-        Ok(())
+        self.draw([NOTE, self.octave_btn[1], 0, 0])?;
+        self.cur_mut().octave = 7 - y;
+        self.octave_btn[1] = (y * 16) + 8;
+        self.draw(self.octave_btn)
     }
 
-    /// Wrapper run function to loop both update and schedule update
-    fn run(&mut self) -> MidiRes {
-        while self.running {
-            self.update()?;
-            self.scheduler.update();
+    /// Dispatch for grid pad presses
+    fn grid_button_dispatch(&mut self, x: u8, y: u8) -> MidiRes {
+        if self.shift_held {
+            return match y {
+                7 => self.toggle_accent(x),
+                _ => self.set_velocity(x, y),
+            };
         }
-        Ok(())
+        // while recording, presses land on the column under
+        // the tracker instead of the column that was pressed
+        let offset = match self.recording {
+            true => self.cur().index,
+            _ => ((self.buffer_index*8) + x) as usize,
+        };
+        // the bottom row clears the column outright
+        if y == 7 {
+            return self.clear_column(offset);
+        }
+        let new_val = 7 - y; // inverting the value
+        self.set_column(offset, new_val)
     }
 
-    /// Called once per cycle to check if the scheduler has
-    /// any messages to process. Since it involves mutation
-    /// of the original &self, we iterate by indexing instead
-    /// of hard referencing an iterator. Clears the queue
-    /// after processing all messages.
-    /// Take note that certain events should only be processed
-    /// if "playing" is set to true.
-    fn update(&mut self) -> MidiRes {
-        if self.scheduler.has_events() {
-            let mut i = 0;
-            while i < self.scheduler.queue.len() {
-                match (self.scheduler.queue[i], self.playing) {
-                    (Msg::Quit, _) => self.quit()?,
-                    (Msg::CheckInputs, _) => self.check_inputs()?,
-                    (Msg::UpdateState, true) => self.update_state()?,
-                    (Msg::FlushNotes, true) => self.flush_notes()?,
-                    _ => {},
-                }
-                i += 1;
-            }
-            self.scheduler.clear_queue();
+    /// Reset a column to its defaults, turning off its LED when
+    /// the column is in the current view
+    fn clear_column(&mut self, col: usize) -> MidiRes {
+        if col >= self.cur().pattern_len {
+            return Ok(());
+        }
+        let old = self.cur().buffer[col];
+        if old.val != 0 && col / 8 == self.buffer_index as usize {
+            self.draw([NOTE, old.note, 0, 0])?;
         }
+        self.cur_mut().buffer[col] = ArpCol::new();
         Ok(())
     }
-}
 
-/// Main function. Create PortMidi context, create Arpeggiator,
+    /// Set a column's value, keeping its LED in sync when the
+    /// column is in the current view
+    fn set_column(&mut self, offset: usize, new_val: u8) -> MidiRes {
+        if offset >= self.cur().pattern_len {
+            return Ok(());
+        }
+        let visible = offset / 8 == self.buffer_index as usize;
+        let note = ((7 - new_val) * 16) + (offset % 8) as u8;
+
+        let old = self.cur().buffer[offset];
+        if old.val != new_val {
+            // turn off old LED if there was a non-zero value
+            if visible && old.val != 0 {
+                self.draw([NOTE, old.note, 0, 0])?;
+            }
+
+            // and turning on the new LED
+            if visible && new_val != 0 {
+                self.draw([NOTE, note, self.grid_io.color(3, 3), 0])?;
+            }
+            let col = &mut self.cur_mut().buffer[offset];
+            col.val = new_val;
+            col.note = note;
+        }
+        Ok(())
+    }
+
+    /// Play the selected lane's views as 8-step segments in the given
+    /// order, or the whole pattern again for an empty chain. Rejects
+    /// views that start past the pattern length.
+    fn set_chain(&mut self, chain: Vec<u8>) -> Result<(), String> {
+        let len = self.cur().pattern_len;
+        if let Some(seg) = chain.iter().find(|seg| (**seg as usize * 8) >= len) {
+            return Err(format!("View {} is past the pattern length {}", seg, len));
+        }
+        self.cur_mut().chain = chain;
+        self.cur_mut().rewind();
+        Ok(())
+    }
+
+    /// Set a column of the selected lane to a value (0 = rest, 1-7),
+    /// keeping its LED in sync. Rejects columns past the pattern
+    /// length and values past 7.
+    fn set_step(&mut self, col: usize, val: u8) -> Result<(), String> {
+        if col >= self.cur().pattern_len {
+            return Err(format!("Column {} is past the pattern length {}", col, self.cur().pattern_len));
+        }
+        if val > 7 {
+            return Err(format!("Invalid step value {}, expected 0-7", val));
+        }
+        self.set_column(col, val).map_err(|e| e.to_string())
+    }
+
+    /// The value of a column of the selected lane, 0 past the end
+    fn get_step(&self, col: usize) -> u8 {
+        match col < self.cur().pattern_len {
+            true => self.cur().buffer[col].val,
+            _ => 0,
+        }
+    }
+
+    /// Record a played note into the column under the tracker,
+    /// snapped to the current scale and key. Degrees that have no
+    /// row on the grid (past the 7th) are skipped.
+    fn record_note(&mut self, note: MidiVal) -> MidiRes {
+        let note = quantize_to_scale(note, &self.scale, self.root) as i32;
+        let rel = note - self.root as i32;
+        let semitone = rel.rem_euclid(12) as u8;
+        let degree = match self.scale.steps().iter().position(|s| *s == semitone) {
+            Some(d) if d < 7 => d as u8,
+            _ => return Ok(()),
+        };
+        let offset = (rel.div_euclid(12) - self.cur().octave as i32).clamp(-4, 4);
+        let index = self.cur().index;
+        self.cur_mut().buffer[index].octave_offset = offset as i8;
+        self.set_column(index, degree + 1)
+    }
+
+    /// Set the velocity of a column in the current view from a
+    /// grid press, the top row being loudest and the bottom softest
+    fn set_velocity(&mut self, x: u8, y: u8) -> MidiRes {
+        self.shift_used = true;
+        let offset = ((self.buffer_index*8) + x) as usize;
+        self.cur_mut().buffer[offset].vel = 127 - (y * 16);
+        Ok(())
+    }
+
+    /// Toggle the accent on a column in the current view
+    fn toggle_accent(&mut self, x: u8) -> MidiRes {
+        self.shift_used = true;
+        let offset = ((self.buffer_index*8) + x) as usize;
+        let accent = !self.cur().accents[offset];
+        self.cur_mut().accents[offset] = accent;
+        if self.tracker.btn[1] != 112 + x || !self.tracker.in_range(self.buffer_index) {
+            self.draw([NOTE, 112 + x, self.bottom_led(x), 0])?;
+        }
+        Ok(())
+    }
+
+    /// What a bottom row pad shows under the tracker: a dim red
+    /// marker on accented columns, otherwise nothing
+    fn bottom_led(&self, x: u8) -> u8 {
+        let offset = ((self.buffer_index*8) + x) as usize;
+        match self.cur().accents[offset] {
+            true => self.grid_io.color(1, 0),
+            _ => 0,
+        }
+    }
+
+    /// Activate the playing mode and toggle the playing LED
+    /// while also deactivating the paused LED
+    fn play(&mut self) -> MidiRes {
+        if !self.playing {
+            self.playing = true;
+            if self.clock_out {
+                self.clock_phase = 0;
+                self.midi_out.write_message([START, 0, 0, 0])?;
+            }
+            self.draw([MIDI, 108, 0, 0])?;
+            self.pp_btn[1] = 109;
+            self.pp_btn[2] = self.grid_io.color(0, 3);
+            self.draw(self.pp_btn)?;
+        }
+        Ok(())
+    }
+
+    /// Start playback from the top of the pattern, as on a
+    /// MIDI Start message
+    fn restart(&mut self) -> MidiRes {
+        for lane in self.lanes.iter_mut() {
+            lane.rewind();
+            lane.clock_acc = 0;
+        }
+        self.click_beat = 0;
+        self.play()
+    }
+
+    /// Inverse action of .play()
+    fn pause(&mut self) -> MidiRes {
+        if self.playing {
+            self.playing = false;
+            self.all_off()?;
+            if self.clock_out {
+                self.midi_out.write_message([STOP, 0, 0, 0])?;
+            }
+            self.draw([MIDI, 109, 0, 0])?;
+            self.pp_btn[1] = 108;
+            self.pp_btn[2] = self.grid_io.color(3, 0);
+            self.draw(self.pp_btn)?;
+        }
+        Ok(())
+    }
+
+    /// Cycle to the next scale and change the active LED to reflect it
+    fn cycle_scale(&mut self) -> MidiRes {
+        self.scale = self.scale.next();
+        self.scale_btn[2] = scale_led(&self.scale, self.grid_io.as_ref());
+        self.draw(self.scale_btn)
+    }
+
+    /// Jump to the nth scale of the cycle (0 = major), wrapping
+    #[cfg(feature = "osc")]
+    fn select_scale(&mut self, n: usize) -> MidiRes {
+        let mut scale = Scale::Major;
+        for _ in 0..n % SCALE_COUNT {
+            scale = scale.next();
+        }
+        self.scale = scale;
+        self.scale_btn[2] = scale_led(&self.scale, self.grid_io.as_ref());
+        self.draw(self.scale_btn)
+    }
+
+    /// Move a lane's note index according to the current direction
+    /// The index wraps at the lane's pattern length
+    /// UpDown bounces off both ends without repeating them,
+    /// Random jumps to any active column (or stays put if none are)
+    fn advance_index(&mut self, lane: usize) {
+        let hold = self.hold;
+        let lane = &mut self.lanes[lane];
+        let (base, len) = lane.segment();
+        let index = lane.index.saturating_sub(base).min(len - 1);
+        let next = match self.direction {
+            Direction::Up => (index + 1) % len,
+            Direction::Down => (index + len - 1) % len,
+            Direction::UpDown if len == 1 => 0,
+            Direction::UpDown => {
+                if lane.descending && index == 0 {
+                    lane.descending = false;
+                } else if !lane.descending && index + 1 == len {
+                    lane.descending = true;
+                }
+                match lane.descending {
+                    true => index - 1,
+                    _ => index + 1,
+                }
+            }
+            Direction::Random => {
+                let active: Vec<usize> = (0..len)
+                    .filter(|&i| lane.playing_buffer(hold)[base + i].val > 0)
+                    .collect();
+                match active.is_empty() {
+                    true => index,
+                    _ => active[self.rng.below(active.len())],
+                }
+            }
+        };
+        // once through a segment a chain moves on to its next one
+        let mut base = base;
+        if !lane.chain.is_empty() {
+            lane.chain_step += 1;
+            if lane.chain_step >= len {
+                lane.chain_step = 0;
+                lane.chain_pos = (lane.chain_pos + 1) % lane.chain.len();
+                base = lane.segment().0;
+            }
+        }
+        lane.index = base + next;
+    }
+
+    /// Latch the current pattern so it keeps playing unchanged while
+    /// columns are edited, or release it back to the live pattern
+    fn toggle_hold(&mut self) -> MidiRes {
+        self.hold = !self.hold;
+        if self.hold {
+            for lane in self.lanes.iter_mut() {
+                lane.hold_buffer = lane.buffer;
+            }
+        }
+        self.render_top_row()
+    }
+
+    /// Arm or disarm step recording
+    fn toggle_recording(&mut self) -> MidiRes {
+        self.recording = !self.recording;
+        self.render_top_row()
+    }
+
+    /// Record a tempo tap and retune the scheduler from the
+    /// average of the most recent taps. A long pause between
+    /// taps starts over with a fresh measurement.
+    fn tap_tempo(&mut self) -> MidiRes {
+        let now = Instant::now();
+        if let Some(last) = self.taps.last() {
+            if now.duration_since(*last) > TAP_TIMEOUT {
+                self.taps.clear();
+            }
+        }
+        if self.taps.len() == 4 {
+            self.taps.remove(0);
+        }
+        self.taps.push(now);
+
+        match tap_bpm(&self.taps) {
+            Some(bpm) => self.set_bpm(bpm.round().clamp(0.0, MAX_BPM as f64) as u16),
+            None => Ok(()),
+        }
+    }
+
+    /// Change the tempo, clamped to the device's BPM range,
+    /// and re-derive the scheduler's tick duration from it
+    fn set_bpm(&mut self, bpm: u16) -> MidiRes {
+        let bpm = bpm.clamp(MIN_BPM, MAX_BPM);
+        if self.scheduler.set_rate(bpm as i32, TICKS_PER_BEAT).is_ok() {
+            self.bpm = bpm;
+        }
+        if self.side_mode == SideMode::Tempo {
+            self.render_side()?;
+        }
+        self.render_top_row()
+    }
+
+    /// Switch to the next arpeggio direction
+    fn cycle_direction(&mut self) -> MidiRes {
+        self.direction = self.direction.next();
+        for lane in self.lanes.iter_mut() {
+            lane.descending = false;
+        }
+        Ok(())
+    }
+
+    /// Update all components that rely on a lane's note tick
+    /// Only the selected lane moves the tracker
+    fn update_state(&mut self, lane: usize) -> MidiRes {
+        // bump the note index counter, unless the step is being stuttered
+        if self.playing && self.stutter != Some(lane) {
+            self.advance_index(lane);
+        }
+        if lane != self.lane {
+            return Ok(());
+        }
+
+        // turn off the tracker's previous LED
+        // do this before we "move" the button
+        self.draw([
+            NOTE, self.tracker.btn[1], self.bottom_led(self.tracker.btn[1] % 16), 0
+        ])?;
+        
+        self.tracker.follow(self.cur().index);
+        
+        // turn on the tracker's LED if it's "on screen"
+        if self.tracker.in_range(self.buffer_index) {
+            self.draw(
+                self.tracker.btn
+            )?;
+        }
+
+        Ok(())
+    }
+
+    /// Pick the MIDI channel (0-15) the selected lane plays on
+    fn set_out_channel(&mut self, channel: u8) -> Result<(), String> {
+        match channel {
+            0..=15 => {
+                self.cur_mut().channel = channel;
+                Ok(())
+            }
+            _ => Err(format!("Invalid MIDI channel {}, expected 0-15", channel)),
+        }
+    }
+
+    /// Set the key the pattern is played in, as a semitone
+    /// offset from C (0 = C, 6 = F#, 11 = B). Wraps past 11.
+    fn set_root(&mut self, root: u8) {
+        self.root = root % 12;
+    }
+
+    /// Turn a semitone offset from a column's scale into the MIDI
+    /// note it should play, applying root, the lane's octave (plus the
+    /// column's own octave offset) and transpose. Returns None for
+    /// notes that would land outside 0..=127.
+    fn pitch_of(&self, lane: usize, col: &ArpCol, offset: u8) -> Option<MidiVal> {
+        let octave = self.lanes[lane].octave as i32 + col.octave_offset as i32;
+        let note = offset as i32 + self.root as i32 + (octave * 12) + self.transpose as i32;
+        match note {
+            0..=127 => Some(note as MidiVal),
+            _ => None,
+        }
+    }
+
+    /// Every pitch a column sounds: one note, or a triad in chord mode
+    /// Chord notes that would land above 127 are dropped
+    fn pitches_of(&self, lane: usize, col: &ArpCol) -> Vec<MidiVal> {
+        let offsets = match self.chord {
+            true => calc_triad(col.val, &self.scale).map(|t| t.to_vec()),
+            _ => calc_note(col.val, &self.scale).map(|n| vec![n]),
+        };
+        offsets
+            .unwrap_or_default()
+            .into_iter()
+            .filter_map(|o| self.pitch_of(lane, col, o))
+            .collect()
+    }
+
+    /// Whether a column's pitch row is allowed to sound: when any
+    /// row is soloed only soloed rows play, otherwise muted rows don't
+    fn row_audible(&self, val: u8) -> bool {
+        let row = match val {
+            1..=7 => (7 - val) as usize,
+            _ => return true,
+        };
+        match self.row_solo.contains(&true) {
+            true => self.row_solo[row],
+            _ => !self.row_muted[row],
+        }
+    }
+
+    /// Sound the current column of a lane
+    fn flush_notes(&mut self, lane: usize) -> MidiRes {
+        // notes held over from the last step end where this one starts
+        self.lane_off(lane)?;
+        let step = self.lanes[lane].step_count;
+        self.lanes[lane].step_count += 1;
+        let col = self.lanes[lane].playing_buffer(self.hold)[self.lanes[lane].index];
+        if !self.row_audible(col.val) {
+            return Ok(());
+        }
+        // roll against the column's trigger probability
+        if (self.rng.below(100) as u8) >= col.prob {
+            return Ok(());
+        }
+        if lane == self.lane {
+            self.flash(self.lanes[lane].index)?;
+        }
+        // swung steps start late and have that much less room
+        let step_ticks = self.lanes[lane].step_ticks;
+        let swing = swing_ticks(SWING_PRESETS[self.swing_idx], step_ticks, step);
+        // a random push of up to `humanize` ms, shared by the whole step
+        let jitter = match self.humanize {
+            0 => 0,
+            ms => {
+                let jitter = self.rng.below(ms as usize + 1) as u64;
+                self.scheduler.ticks_for(Duration::from_millis(jitter))
+            }
+        };
+        let vel = match self.lanes[lane].accents[self.lanes[lane].index] {
+            true => ACCENT_VEL,
+            _ => col.vel,
+        };
+        let vel = shape_velocity(vel, &self.vel_curve);
+        let gate = self.pick_gate();
+        let ratchet = col.ratchet.clamp(1, 4) as usize;
+        let ratchet = match self.stutter == Some(lane) {
+            true => ratchet.max(STUTTER_REPEATS),
+            _ => ratchet,
+        };
+        let sub = (step_ticks.saturating_sub(swing) / ratchet).max(1);
+        // the push never reaches the next repeat, or the next step
+        let delay = swing + jitter.min(sub - 1);
+        for note in self.pitches_of(lane, &col) {
+            // repeats are spread evenly across the step, each one
+            // released before the next so nothing bleeds over
+            for k in 0..ratchet {
+                match k * sub + delay {
+                    0 => self.note_on(lane, note, vel)?,
+                    on_ticks => {
+                        self.scheduler.after(on_ticks, Msg::NoteOn(lane, note, vel));
+                    }
+                }
+                if ratchet > 1 || gate < 1.0 {
+                    let off_ticks = k * sub + delay + gate_ticks(gate, sub);
+                    self.scheduler.after(off_ticks, Msg::NoteOff(lane, note));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Light the pad of the column that plays, if it's in view, and
+    /// schedule it back to normal. While holding that's the snapshot's
+    /// column, which may not be the one the grid shows.
+    fn flash(&mut self, index: usize) -> MidiRes {
+        let col = self.cur().playing_buffer(self.hold)[index];
+        if col.val == 0 || index / 8 != self.buffer_index as usize {
+            return Ok(());
+        }
+        self.draw([NOTE, col.note, self.grid_io.color(0, 3), 0])?;
+        self.scheduler.after(FLASH_TICKS, Msg::FlashOff(index));
+        Ok(())
+    }
+
+    /// Put a flashed pad back to whatever the grid shows there now,
+    /// in case the column was edited or scrolled away meanwhile
+    fn flash_off(&mut self, index: usize) -> MidiRes {
+        let col = self.cur().playing_buffer(self.hold)[index];
+        if col.val == 0 || index / 8 != self.buffer_index as usize {
+            return Ok(());
+        }
+        self.draw([NOTE, col.note, self.pad_led(col.note), 0])
+    }
+
+    /// The color `draw_ui()` gives a pad: a set column on top of the
+    /// tracker, on top of the accent markers
+    fn pad_led(&self, note: u8) -> u8 {
+        let x = note % 16;
+        let index = (self.buffer_index * 8 + x) as usize;
+        let col = self.cur().buffer[index];
+        if col.val > 0 && col.note == note {
+            return self.grid_io.color(3, 3);
+        }
+        if self.tracker.btn[1] == note && self.tracker.in_range(self.buffer_index) {
+            return self.tracker.btn[2];
+        }
+        match note / 16 {
+            7 => self.bottom_led(x),
+            _ => 0,
+        }
+    }
+
+    /// Fill the first `steps` columns with an Euclidean rhythm:
+    /// `hits` columns set to `value`, spread evenly, the rest cleared
+    fn fill_euclidean(&mut self, hits: usize, steps: usize, value: u8) -> MidiRes {
+        let steps = steps.min(MAX_STEPS);
+        let value = value.min(7);
+        for (i, hit) in euclidean(hits, steps).into_iter().enumerate() {
+            let val = match hit {
+                true => value,
+                _ => 0,
+            };
+            let col = &mut self.cur_mut().buffer[i];
+            col.val = val;
+            col.note = ((7 - val) * 16) + (i % 8) as u8;
+        }
+        self.render_ui()
+    }
+
+    /// Fill the pattern with random values: roughly `density` percent
+    /// of the columns get a value from 1 to 7, the rest are cleared
+    fn randomize(&mut self, density: u8) -> MidiRes {
+        for i in 0..self.cur().pattern_len {
+            let val = match (self.rng.below(100) as u8) < density {
+                true => 1 + self.rng.below(7) as u8,
+                _ => 0,
+            };
+            let col = &mut self.cur_mut().buffer[i];
+            col.val = val;
+            col.note = ((7 - val) * 16) + (i % 8) as u8;
+        }
+        self.render_ui()
+    }
+
+    /// Point every column's LED note back at its own position,
+    /// after columns have been moved around
+    fn sync_notes(&mut self) {
+        for (i, col) in self.cur_mut().buffer.iter_mut().enumerate() {
+            col.note = ((7 - col.val) * 16) + (i % 8) as u8;
+        }
+    }
+
+    /// Play the pattern backwards: reverse the order of the columns
+    /// within the pattern length
+    fn reverse(&mut self) -> MidiRes {
+        let len = self.cur().pattern_len;
+        self.cur_mut().buffer[..len].reverse();
+        self.sync_notes();
+        self.render_ui()
+    }
+
+    /// Copy the 8 columns of one view onto another. Views that don't
+    /// exist, or start past the pattern length, are left alone.
+    fn copy_view(&mut self, from: u8, to: u8) -> MidiRes {
+        let len = self.cur().pattern_len;
+        let (from, to) = (from as usize * 8, to as usize * 8);
+        if from == to || from >= len || to >= len {
+            return Ok(());
+        }
+        self.cur_mut().buffer.copy_within(from..from + 8, to);
+        self.sync_notes();
+        self.render_ui()
+    }
+
+    /// Shift every column of the pattern by `steps`, wrapping around
+    /// the pattern length. Positive moves right, negative left.
+    fn rotate(&mut self, steps: i32) -> MidiRes {
+        let len = self.cur().pattern_len;
+        let by = steps.rem_euclid(len as i32) as usize;
+        self.cur_mut().buffer[..len].rotate_right(by);
+        self.sync_notes();
+        self.render_ui()
+    }
+
+    /// Flip every note column upside down, so the highest degree
+    /// becomes the lowest (1 <-> 7, 2 <-> 6 ...). Rests stay rests.
+    fn invert_values(&mut self) -> MidiRes {
+        let len = self.cur().pattern_len;
+        for col in self.cur_mut().buffer[..len].iter_mut() {
+            if col.val > 0 {
+                col.val = 8 - col.val;
+            }
+        }
+        self.sync_notes();
+        self.render_ui()
+    }
+
+    /// Save the selected lane's pattern as plain text: the scale's semitone offsets,
+    /// root, octave, then one `col val vel octave_offset prob ratchet`
+    /// line per column
+    fn save_pattern(&self, path: &str) -> std::io::Result<()> {
+        let steps: Vec<String> = self.scale.steps().iter().map(|s| s.to_string()).collect();
+        let mut out = String::from("# lparp pattern\n");
+        out.push_str(&format!("scale {}\n", steps.join(" ")));
+        out.push_str(&format!("root {}\n", self.root));
+        out.push_str(&format!("octave {}\n", self.cur().octave));
+        for col in self.cur().buffer.iter() {
+            out.push_str(&format!(
+                "col {} {} {} {} {}\n",
+                col.val, col.vel, col.octave_offset, col.prob, col.ratchet
+            ));
+        }
+        std::fs::write(path, out)
+    }
+
+    /// Load a pattern written by `save_pattern()` into the selected
+    /// lane and redraw the grid
+    /// Nothing is changed unless the whole file parses cleanly
+    fn load_pattern(&mut self, path: &str) -> Result<(), String> {
+        let text = std::fs::read_to_string(path)
+            .map_err(|e| format!("Failed to read {}: {}", path, e))?;
+
+        let mut scale: Option<Scale> = None;
+        let mut root = self.root;
+        let mut octave = self.cur().octave;
+        let mut buffer = [ArpCol::new(); MAX_STEPS];
+        let mut cols = 0;
+
+        for (n, line) in text.lines().enumerate() {
+            let bad = || format!("{}:{}: malformed line '{}'", path, n + 1, line);
+            let mut words = line.split_whitespace();
+            let key = match words.next() {
+                Some(k) if !k.starts_with('#') => k,
+                _ => continue,
+            };
+            let vals: Vec<i32> = words
+                .map(|w| w.parse::<i32>())
+                .collect::<Result<_, _>>()
+                .map_err(|_| bad())?;
+            match (key, vals.as_slice()) {
+                ("scale", steps) if !steps.is_empty() && steps.iter().all(|s| (0..12).contains(s)) => {
+                    let steps: Vec<u8> = steps.iter().map(|s| *s as u8).collect();
+                    scale = Some(Scale::from_steps(&steps));
+                }
+                ("root", [r]) if (0..12).contains(r) => root = *r as u8,
+                ("octave", [o]) if (0..8).contains(o) => octave = *o as u8,
+                ("col", [val, vel, off, prob, ratchet])
+                    if cols < MAX_STEPS
+                        && (0..8).contains(val)
+                        && (0..128).contains(vel)
+                        && (-4..5).contains(off)
+                        && (0..101).contains(prob)
+                        && (1..5).contains(ratchet) =>
+                {
+                    let col = &mut buffer[cols];
+                    col.val = *val as u8;
+                    col.note = ((7 - col.val) * 16) + (cols % 8) as u8;
+                    col.vel = *vel as u8;
+                    col.octave_offset = *off as i8;
+                    col.prob = *prob as u8;
+                    col.ratchet = *ratchet as u8;
+                    cols += 1;
+                }
+                _ => return Err(bad()),
+            }
+        }
+        if cols != MAX_STEPS {
+            return Err(format!("{}: expected {} columns, found {}", path, MAX_STEPS, cols));
+        }
+
+        self.cur_mut().buffer = buffer;
+        self.root = root;
+        self.cur_mut().octave = octave;
+        self.octave_btn[1] = ((7 - octave) * 16) + 8;
+        if let Some(scale) = scale {
+            self.scale = scale;
+            self.scale_btn[2] = scale_led(&self.scale, self.grid_io.as_ref());
+        }
+        self.render_ui().map_err(|e| format!("Failed to redraw: {}", e))
+    }
+
+    /// Write the pattern out as a type-0 Standard MIDI File,
+    /// one step per column in playing order, empty columns as rests
+    /// Every lane is written on its own channel, and every note
+    /// lasts the middle of the gate range rather than a random gate
+    fn export_smf(&self, path: &str) -> std::io::Result<()> {
+        let mut events: Vec<SmfEvent> = Vec::new();
+        let gate = (self.gate_min + self.gate_max) / 2.0;
+        for (l, lane) in self.lanes.iter().enumerate() {
+            let step = lane.step_ticks;
+            let len = match gate < 1.0 {
+                true => gate_ticks(gate, step),
+                _ => step,
+            };
+            for (i, col) in lane.buffer[..lane.pattern_len].iter().enumerate() {
+                for note in self.pitches_of(l, col) {
+                    let start = (i * step) as u32;
+                    events.push(SmfEvent { tick: start, status: NOTE | lane.channel, data1: note, data2: col.vel });
+                    events.push(SmfEvent { tick: start + len as u32, status: NOTE_OFF | lane.channel, data1: note, data2: 0 });
+                }
+            }
+        }
+        smf::write_type0(path, &events, TICKS_PER_BEAT as u16, self.bpm)
+    }
+
+    /// A gate for the next step, anywhere between `gate_min` and
+    /// `gate_max`; equal bounds always give the same gate
+    fn pick_gate(&mut self) -> f32 {
+        let (lo, hi) = (self.gate_min.min(self.gate_max), self.gate_max.max(self.gate_min));
+        match hi > lo {
+            true => lo + (hi - lo) * self.rng.below(1001) as f32 / 1000.0,
+            _ => lo,
+        }
+    }
+
+    /// Play a single note on a lane's channel
+    /// Mirrored as an OSC `/note` when OSC output is on, and
+    /// steals the lane's oldest note past `max_voices`
+    fn note_on(&mut self, lane: usize, note: MidiVal, vel: MidiVal) -> MidiRes {
+        #[cfg(feature = "osc")]
+        if let Some(osc) = &self.osc_out {
+            // visuals missing a note shouldn't stop the music
+            let _ = osc.send_note(note, vel);
+        }
+        let lane = &mut self.lanes[lane];
+        match lane.active_notes.iter().position(|n| *n == note) {
+            // a re-triggered note counts as the newest again
+            Some(i) => {
+                lane.active_notes.remove(i);
+            }
+            // past the voice limit the longest-held note is stolen
+            None if self.max_voices > 0 && lane.active_notes.len() >= self.max_voices => {
+                let oldest = lane.active_notes.remove(0);
+                self.midi_out.write_message([NOTE_OFF | lane.channel, oldest, 0, 0])?;
+            }
+            None => {}
+        }
+        lane.active_notes.push(note);
+        self.midi_out.write_message([NOTE | lane.channel, note, vel, 1])
+    }
+
+    /// Called every scheduler tick. Spreads 24 MIDI clock pulses
+    /// evenly across the ticks of a beat when clock output is on
+    fn clock_tick(&mut self) -> MidiRes {
+        let ticks = TICKS_PER_BEAT as usize;
+        let before = (self.clock_phase * CLOCK_PPQN) / ticks;
+        self.clock_phase = (self.clock_phase + 1) % ticks;
+        let after = match self.clock_phase {
+            0 => CLOCK_PPQN,
+            _ => (self.clock_phase * CLOCK_PPQN) / ticks,
+        };
+        if self.clock_out && after != before {
+            self.midi_out.write_message([CLOCK, 0, 0, 0])?;
+        }
+        Ok(())
+    }
+
+    /// Sound the metronome on each quarter note, louder on the
+    /// first beat of every bar
+    fn click(&mut self) -> MidiRes {
+        if self.show_position && self.playing {
+            let (bar, beat, _) = self.position();
+            println!("bar {} beat {}", bar + 1, beat + 1);
+        }
+        let beat = self.click_beat;
+        self.click_beat = (self.click_beat + 1) % BEATS_PER_BAR;
+        if !self.metronome {
+            return Ok(());
+        }
+        let vel = match beat {
+            0 => 127,
+            _ => 70,
+        };
+        self.midi_out.write_message([NOTE | self.click_channel, self.click_note, vel, 0])?;
+        self.scheduler.after(CLICK_TICKS, Msg::ClickOff(self.click_note));
+        Ok(())
+    }
+
+    /// Where the selected lane is as (bar, beat, tick_in_beat), from
+    /// the step it last played since it was rewound, a bar being one
+    /// pass of its pattern
+    fn position(&self) -> (usize, usize, usize) {
+        let lane = self.cur();
+        let tick = lane.step_count.saturating_sub(1) * lane.step_ticks;
+        bar_position(tick, lane.pattern_len * lane.step_ticks, TICKS_PER_BEAT as usize)
+    }
+
+    /// Silence everything on every lane's channel: All Notes Off
+    /// (CC 123), plus a note-off for each sounding note in case the
+    /// receiver ignores the controller
+    fn panic(&mut self) -> MidiRes {
+        self.all_off()?;
+        for i in 0..self.lanes.len() {
+            let channel = self.lanes[i].channel;
+            self.midi_out.write_message([MIDI | channel, 123, 0, 0])?;
+        }
+        Ok(())
+    }
+
+    /// Release a single note on a lane's channel
+    fn note_off(&mut self, lane: usize, note: MidiVal) -> MidiRes {
+        let lane = &mut self.lanes[lane];
+        lane.active_notes.retain(|n| *n != note);
+        self.midi_out.write_message([NOTE_OFF | lane.channel, note, 0, 0])
+    }
+
+    /// Release every note a lane has started and not yet stopped
+    fn lane_off(&mut self, lane: usize) -> MidiRes {
+        let lane = &mut self.lanes[lane];
+        for note in std::mem::take(&mut lane.active_notes) {
+            self.midi_out.write_message([NOTE_OFF | lane.channel, note, 0, 0])?;
+        }
+        Ok(())
+    }
+
+    /// Release every note the arp has started and not yet stopped
+    fn all_off(&mut self) -> MidiRes {
+        for i in 0..self.lanes.len() {
+            self.lane_off(i)?;
+        }
+        Ok(())
+    }
+
+    /// Light an LED described Launchpad-style, either a top row
+    /// CC (104-111) or a grid note (y*16 + x, side column at x == 8)
+    /// The change only lands in the frame, `flush_leds()` sends it
+    fn draw(&mut self, btn: BtnArr) -> MidiRes {
+        let (x, y) = (btn[1] % 16, btn[1] / 16);
+        let slot = match btn[0] {
+            MIDI if (104..112).contains(&btn[1]) => 72 + (btn[1] - 104),
+            MIDI => return Ok(()),
+            _ if x < 8 && y < 8 => (y * 8) + x,
+            _ if x == 8 && y < 8 => 64 + y,
+            _ => return Ok(()),
+        };
+        self.leds[slot as usize] = btn[2];
+        Ok(())
+    }
+
+    /// Send only the LEDs that differ from what the device shows,
+    /// or the whole frame when that takes fewer messages
+    fn flush_leds(&mut self) -> MidiRes {
+        let changed = (0..FRAME_LEN).filter(|i| self.leds[*i] != self.led_state[*i]).count();
+        if changed > self.grid_io.frame_messages() {
+            return self.flush_frame();
+        }
+        for i in 0..FRAME_LEN {
+            let color = self.leds[i];
+            if color == self.led_state[i] {
+                continue;
+            }
+            let n = i as u8;
+            match n {
+                0..=63 => self.grid_io.set_led(n % 8, n / 8, color)?,
+                64..=71 => self.grid_io.set_led(8, n - 64, color)?,
+                _ => self.grid_io.set_top_led(n - 72, color)?,
+            }
+            self.led_state[i] = color;
+        }
+        Ok(())
+    }
+
+    /// Push the whole frame at once, whatever the device shows
+    fn flush_frame(&mut self) -> MidiRes {
+        self.grid_io.flush_frame(&self.leds)?;
+        self.led_state = self.leds;
+        Ok(())
+    }
+
+    /// Clears the board of all LED values
+    fn clear_board(&mut self) -> MidiRes {
+        self.leds = [0; FRAME_LEN];
+        self.led_state = [0; FRAME_LEN];
+        self.grid_io.clear()
+    }
+
+    /// Main function to re-draw every element onto the device.
+    /// Builds the whole frame from scratch, then sends what changed
+    /// in one go so switching views doesn't flicker.
+    fn render_ui(&mut self) -> MidiRes {
+        self.draw_ui()?;
+        self.flush_leds()
+    }
+
+    /// Same as `render_ui()`, but pushes the whole frame whatever the
+    /// device is thought to show, e.g. when it was just (re)opened
+    fn redraw(&mut self) -> MidiRes {
+        self.draw_ui()?;
+        self.flush_frame()
+    }
+
+    /// Build the whole UI into the frame without sending anything
+    fn draw_ui(&mut self) -> MidiRes {
+        // start from a blank frame
+        self.leds = [0; FRAME_LEN];
+
+        // draw UI elements
+        self.draw(self.buffer_btn)?;
+        self.draw(self.pp_btn)?;
+        self.draw(self.scale_btn)?;
+        self.render_side()?;
+
+        // accent markers sit on the bottom row, under the tracker
+        for x in 0..8 {
+            self.draw([NOTE, 112 + x, self.bottom_led(x), 0])?;
+        }
+
+        // draw tracker if it's on screen
+        // note: this part works
+        if self.tracker.in_range(self.buffer_index) {
+            self.draw(self.tracker.btn)?;
+        }
+        
+        // render all cells
+        for c in 0..8 {
+            let index = ((self.buffer_index*8) + c) as usize;
+            let col = &self.cur().buffer[index];
+            if col.val > 0 {
+                let color = self.grid_io.color(3, 3);
+                self.draw([NOTE, col.note, color, 0])?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Wrapper run function to loop both update and schedule update
+    /// A device error pauses the loop while the devices are reopened
+    fn run(&mut self) -> MidiRes {
+        while self.running {
+            if let Err(e) = self.update() {
+                self.reconnect(e)?;
+            }
+            self.scheduler.update();
+        }
+        Ok(())
+    }
+
+    /// Called once per cycle to check if the scheduler has
+    /// any messages to process. Since it involves mutation
+    /// of the original &self, we take ownership of the queued
+    /// messages first, which leaves the queue empty once
+    /// everything has been processed.
+    /// Take note that certain events should only be processed
+    /// if "playing" is set to true, and that note steps are left
+    /// to the external clock while it is driving playback.
+    fn update(&mut self) -> MidiRes {
+        if self.scheduler.has_events() {
+            let internal = !self.external_clock();
+            for msg in self.scheduler.take_events() {
+                match (msg, self.playing) {
+                    (Msg::Quit, _) => self.quit()?,
+                    (Msg::CheckInputs, _) => self.check_inputs()?,
+                    (Msg::UpdateState(lane), true) if internal => self.update_state(lane)?,
+                    (Msg::FlushNotes(lane), true) if internal => self.flush_notes(lane)?,
+                    (Msg::NoteOn(lane, note, vel), true) => self.note_on(lane, note, vel)?,
+                    (Msg::NoteOff(lane, note), _) => self.note_off(lane, note)?,
+                    (Msg::Clock, _) => self.clock_tick()?,
+                    (Msg::Click, _) => self.click()?,
+                    (Msg::ClickOff(note), _) => {
+                        self.midi_out.write_message([NOTE_OFF | self.click_channel, note, 0, 0])?
+                    }
+                    (Msg::FlashOff(index), _) => self.flash_off(index)?,
+                    _ => {},
+                }
+            }
+            self.flush_leds()?;
+        }
+        Ok(())
+    }
+}
+
+/// Don't leave notes hanging if the arp goes away mid-phrase
+impl Drop for Arp<'_> {
+    fn drop(&mut self) {
+        let _ = self.all_off();
+    }
+}
+
+/// Value following a `--flag` on the command line, if given
+fn arg_value(flag: &str) -> Option<String> {
+    let args: Vec<String> = std::env::args().collect();
+    args.iter()
+        .position(|a| a == flag)
+        .and_then(|i| args.get(i + 1).cloned())
+}
+
+/// Every value following a repeatable `--flag`, in order
+fn arg_values(flag: &str) -> Vec<String> {
+    let args: Vec<String> = std::env::args().collect();
+    args.windows(2)
+        .filter(|w| w[0] == flag)
+        .map(|w| w[1].clone())
+        .collect()
+}
+
+/// Whether a bare `--flag` was passed on the command line
+fn has_flag(flag: &str) -> bool {
+    std::env::args().any(|a| a == flag)
+}
+
+/// A `--lane` step length: a subdivision name, or ticks at
+/// `LANE_TICKS_PER_BEAT` a quarter, rounded to the nearest tick
+fn lane_step(spec: &str) -> Option<usize> {
+    if let Some(sub) = Subdivision::from_name(spec) {
+        return Some(sub.ticks());
+    }
+    let ticks = spec.parse::<usize>().ok().filter(|t| *t > 0)?;
+    let scaled = (ticks * TICKS_PER_BEAT as usize + LANE_TICKS_PER_BEAT / 2) / LANE_TICKS_PER_BEAT;
+    Some(scaled.max(1))
+}
+
+/// A comma-separated list, or None if any entry doesn't parse
+fn parse_list<T: std::str::FromStr>(spec: &str) -> Option<Vec<T>> {
+    spec.split(',').map(|n| n.trim().parse().ok()).collect()
+}
+
+/// Parse a command line value with `parse`, describing a bad one by
+/// its flag name and what was `expected` instead
+fn parse_arg<T>(
+    flag: &str,
+    val: Option<String>,
+    expected: &str,
+    parse: impl FnOnce(&str) -> Option<T>,
+) -> Result<Option<T>, String> {
+    match val {
+        Some(val) => match parse(&val) {
+            Some(parsed) => Ok(Some(parsed)),
+            None => Err(format!("Invalid {} value '{}', expected {}", flag, val, expected)),
+        },
+        None => Ok(None),
+    }
+}
+
+/// Collects every bad command line value, so they can all be
+/// reported at once before any device is opened
+#[derive(Default)]
+struct Args {
+    errors: Vec<String>,
+}
+
+impl Args {
+    /// The value following `--flag` run through `parse`. A value it
+    /// rejects, or a flag given without one, is noted as an error.
+    fn get<T>(&mut self, flag: &str, expected: &str, parse: impl FnOnce(&str) -> Option<T>) -> Option<T> {
+        let val = arg_value(flag);
+        if val.is_none() && has_flag(flag) {
+            self.errors.push(format!("Missing value for {}, expected {}", flag, expected));
+        }
+        self.note(parse_arg(flag, val, expected, parse))
+    }
+
+    /// Every value of a repeatable `--flag` run through `parse`,
+    /// noting each one it rejects as an error
+    fn get_all<T>(&mut self, flag: &str, expected: &str, parse: impl Fn(&str) -> Option<T>) -> Vec<T> {
+        arg_values(flag)
+            .into_iter()
+            .filter_map(|val| self.note(parse_arg(flag, Some(val), expected, &parse)))
+            .collect()
+    }
+
+    fn note<T>(&mut self, res: Result<Option<T>, String>) -> Option<T> {
+        res.unwrap_or_else(|e| {
+            self.errors.push(e);
+            None
+        })
+    }
+}
+
+/// Main function. Create PortMidi context, create Arpeggiator,
 /// run application loop, then close out.
 fn main() -> MidiRes {
     let ctx = pm::PortMidi::new()?;
-    let target: &str = "Midi Through Port-0";
-    let dev = Device::new(&target, &ctx).expect("Failed");
 
-    let lpname: &str = "Launchpad MIDI 1";
-    let lp = Device::new(&lpname, &ctx).expect("Failed");
+    let mut args = Args::default();
+    let name = |s: &str| Some(s.to_string());
+    let channel = |s: &str| s.parse::<u8>().ok().filter(|c| *c < 16);
+    let controller = |s: &str| s.parse::<u8>().ok().filter(|c| *c < 120);
+
+    let mut config = ArpConfig::default();
+    if let Some(name) = args.get("--grid", "a port name", name) {
+        config.grid_name = name;
+    }
+    if let Some(name) = args.get("--out", "a port name", name) {
+        config.out_name = name;
+    }
+    if let Some(bpm) = args.get("--bpm", "a tempo in BPM", |s| s.parse().ok()) {
+        config.bpm = bpm;
+    }
+    if let Some(model) = args.get("--model", "mk1, mk2 or mk3", LaunchpadModel::from_name) {
+        config.model = model;
+    }
+    if let Some(channel) = args.get("--channel", "a channel 0-15", channel) {
+        config.out_channel = channel;
+    }
+    #[cfg(feature = "osc")]
+    {
+        config.osc_out = args.get("--osc-out", "a host:port", name);
+        config.osc_in = args.get("--osc-in", "a host:port", name);
+    }
+    let cc_num = args.get("--cc", "a controller 0-119", controller);
+    let pressure_cc = args.get("--pressure-cc", "a controller 0-119", controller);
+    let click = args.get("--click", "<channel 0-15>,<note 0-127>", |s| {
+        match parse_list::<u8>(s)?.as_slice() {
+            [channel, note] if *channel < 16 && *note < 128 => Some((*channel, *note)),
+            _ => None,
+        }
+    });
+    let humanize = args.get("--humanize", "milliseconds 0-255", |s| s.parse().ok());
+    let gate = args.get("--gate", "<length>[,<max length>] from 0.0 to 1.0", |s| {
+        let gates = parse_list::<f32>(s)?;
+        let in_range = gates.iter().all(|g| (0.0..=1.0).contains(g));
+        match gates.as_slice() {
+            [gate] if in_range => Some((*gate, *gate)),
+            [min, max] if in_range => Some((*min, *max)),
+            _ => None,
+        }
+    });
+    let max_voices = args.get("--max-voices", "a voice count", |s| s.parse().ok());
+    let vel_curve = args.get("--velocity-curve", "linear, exp or log", VelocityCurve::from_name);
+    let chain = args.get("--chain", "<view>,<view>,... with views 0-3", |s| {
+        parse_list::<u8>(s).filter(|segs| segs.iter().all(|seg| *seg < 4))
+    });
+    let subdivision = args.get("--subdivision", "quarter, eighth, triplet or sixteenth", Subdivision::from_name);
+    let lanes = args.get_all("--lane", "<channel 0-15>,<subdivision or ticks>[,<octave>]", |s| {
+        let parts: Vec<&str> = s.split(',').map(|p| p.trim()).collect();
+        let channel = parts[0].parse::<u8>().ok().filter(|c| *c < 16)?;
+        match parts[1..] {
+            [step] => Some((channel, lane_step(step)?, 5)),
+            [step, octave] => Some((channel, lane_step(step)?, octave.parse::<u8>().ok()?.min(7))),
+            _ => None,
+        }
+    });
+    let seed = args.get("--seed", "a number", |s| s.parse().ok());
+    let euclid = args.get("--euclid", "<hits>,<steps>[,<value>]", |s| {
+        match parse_list::<usize>(s)?.as_slice() {
+            [hits, steps] => Some((*hits, *steps, 4)),
+            [hits, steps, value] => Some((*hits, *steps, (*value).min(7) as u8)),
+            _ => None,
+        }
+    });
+    let steps = args.get("--steps", "step values 0-7 separated by commas", |s| {
+        parse_list::<u8>(s).filter(|vals| vals.len() <= config.pattern_len && vals.iter().all(|v| *v < 8))
+    });
+    let load = args.get("--load", "a file name", name);
+    let save = args.get("--save", "a file name", name);
+    let export = args.get("--export", "a file name", name);
+    if !args.errors.is_empty() {
+        for e in &args.errors {
+            println!("{}", e);
+        }
+        return Ok(());
+    }
 
-    let mut arp = Arp::new(dev, lp);
+    let mut arp = match Arp::from_config(config, &ctx) {
+        Ok(arp) => arp,
+        Err(e) => {
+            println!("{}", e);
+            println!("Available MIDI ports:");
+            for (id, name, is_input, is_output) in Device::list_devices(&ctx) {
+                let kind = match (is_input, is_output) {
+                    (true, true) => "in/out",
+                    (true, _) => "in",
+                    _ => "out",
+                };
+                println!("  {}: {} ({})", id, name, kind);
+            }
+            return Ok(());
+        }
+    };
+    arp.chord = has_flag("--chord");
+    if let Some(cc) = cc_num {
+        arp.cc_num = cc;
+    }
+    arp.pressure_cc = pressure_cc;
+    arp.metronome = has_flag("--metronome");
+    arp.clock_out = has_flag("--clock-out");
+    arp.clock_in = has_flag("--clock-in");
+    arp.show_position = has_flag("--position");
+    if let Some((channel, note)) = click {
+        arp.click_channel = channel;
+        arp.click_note = note;
+    }
+    if let Some(ms) = humanize {
+        arp.humanize = ms;
+    }
+    if let Some((min, max)) = gate {
+        (arp.gate_min, arp.gate_max) = (min, max);
+    }
+    if let Some(n) = max_voices {
+        arp.max_voices = n;
+    }
+    if let Some(curve) = vel_curve {
+        arp.vel_curve = curve;
+    }
+    if let Some(sub) = subdivision {
+        arp.lanes[0].step_ticks = sub.ticks();
+    }
+    for (channel, ticks, octave) in lanes {
+        if let Err(e) = arp.add_lane(channel, ticks, octave) {
+            println!("{}", e);
+        }
+    }
+    if let Some(seed) = seed {
+        arp.rng = Rng::new(seed);
+    }
 
     // (1s / BPM) / NTICKS = tick duration 
-    // 60 / 120 = 0.5 / 64 = 0.007
-    arp.scheduler.set_rate(120, 64);
-    arp.scheduler.interval(4, Msg::CheckInputs);
-    arp.scheduler.interval(32, Msg::UpdateState);
-    arp.scheduler.interval(32, Msg::FlushNotes);
-
-    // 1 = every tick, or 256th note
-    // 2 = 128th
-    // 4 = 64th
-    // 8 = 32nd
-    // 16 = sixteenth
-    // 32 = eigth
-    // 64 = quarter note (bass drum)
-    // 128 = half note (snare drum)
-    // 256 = full note (two "beats")
-
-    println!("Beginning program");
+    // 60 / 120 = 0.5 / 96 = 0.0052
+    arp.scheduler.set_rate(arp.bpm as i32, TICKS_PER_BEAT).expect("Invalid tempo");
+    arp.scheduler.interval(6, Msg::CheckInputs);
+    arp.schedule_lanes();
+    arp.scheduler.interval(1, Msg::Clock);
+    arp.scheduler.interval(TICKS_PER_BEAT as usize, Msg::Click);
+
+    // 1 = every tick, or 384th note
+    // 6 = 64th
+    // 12 = 32nd
+    // 24 = sixteenth
+    // 32 = eighth triplet
+    // 48 = eighth
+    // 96 = quarter note (bass drum)
+    // 192 = half note (snare drum)
+    // 384 = whole note (four beats)
+
     let before = Instant::now();
 
+    arp.grid_io.flush_input()?;
     arp.clear_board()?;
-    arp.render_ui()?;
+    arp.redraw()?;
+    if let Some(path) = load {
+        if let Err(e) = arp.load_pattern(&path) {
+            println!("{}", e);
+        }
+    }
+    // checked against the pattern as loaded
+    if let Some(segs) = chain {
+        if let Err(e) = arp.set_chain(segs) {
+            println!("{}", e);
+        }
+    }
+    if let Some((hits, steps, value)) = euclid {
+        arp.fill_euclidean(hits, steps, value)?;
+    }
+    if let Some(vals) = steps {
+        for (col, val) in vals.into_iter().enumerate() {
+            if let Err(e) = arp.set_step(col, val) {
+                println!("{}", e);
+            }
+        }
+        arp.flush_leds()?;
+    }
     arp.run()?;
     arp.clear_board()?;
 
+    // print the pattern back in a form `--steps` takes
+    let steps: Vec<String> = (0..arp.cur().pattern_len)
+        .map(|col| arp.get_step(col).to_string())
+        .collect();
+    println!("Pattern: --steps {}", steps.join(","));
+
+    if let Some(path) = save {
+        match arp.save_pattern(&path) {
+            Ok(_) => println!("Pattern saved to {}", path),
+            Err(e) => println!("Failed to save pattern: {}", e),
+        }
+    }
+    if let Some(path) = export {
+        match arp.export_smf(&path) {
+            Ok(_) => println!("Pattern exported to {}", path),
+            Err(e) => println!("Failed to export pattern: {}", e),
+        }
+    }
+
     let after = before.elapsed();
     println!("Program end. Time passed: {:?}", after.as_secs());
     Ok(())
 }
 
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// An arp on mock devices, with a clone of the output to inspect
+    fn mock_arp() -> (Arp<'static>, MockDevice) {
+        let out = MockDevice::new();
+        let arp = Arp::new(Box::new(out.clone()), Box::new(MockGrid::new()));
+        (arp, out)
+    }
+
+    #[test]
+    fn lists_parse_whole_or_not_at_all() {
+        assert_eq!(parse_list::<u8>("1, 2,3"), Some(vec![1, 2, 3]));
+        assert_eq!(parse_list::<u8>("1,x,3"), None);
+        assert_eq!(parse_list::<u8>("1,,3"), None);
+        assert_eq!(parse_list::<u8>("300"), None);
+    }
+
+    #[test]
+    fn bad_values_are_reported_by_flag_name() {
+        let channel = |s: &str| s.parse::<u8>().ok().filter(|c| *c < 16);
+        assert_eq!(parse_arg("--channel", Some("9".into()), "a channel 0-15", channel), Ok(Some(9)));
+        assert_eq!(parse_arg("--channel", None, "a channel 0-15", channel), Ok(None));
+        assert_eq!(
+            parse_arg("--channel", Some("16".into()), "a channel 0-15", channel),
+            Err("Invalid --channel value '16', expected a channel 0-15".to_string())
+        );
+        assert_eq!(
+            parse_arg("--bpm", Some("fast".into()), "a tempo in BPM", |s| s.parse::<u16>().ok()),
+            Err("Invalid --bpm value 'fast', expected a tempo in BPM".to_string())
+        );
+    }
+
+    #[test]
+    fn swing_presses_cycle_the_presets() {
+        let (mut arp, _) = mock_arp();
+        let mut ratios = vec![SWING_PRESETS[arp.swing_idx]];
+        for _ in 0..SWING_PRESETS.len() {
+            arp.side_function(5).unwrap();
+            ratios.push(SWING_PRESETS[arp.swing_idx]);
+        }
+        assert_eq!(ratios, vec![0.5, 0.54, 0.58, 0.62, 2.0 / 3.0, 0.5]);
+    }
+
+    #[test]
+    fn swing_delays_every_second_step() {
+        assert_eq!(swing_ticks(0.5, 48, 1), 0);
+        assert_eq!(swing_ticks(0.4, 48, 1), 0);
+        assert_eq!(swing_ticks(2.0 / 3.0, 48, 0), 0);
+        assert_eq!(swing_ticks(2.0 / 3.0, 48, 1), 16);
+        assert_eq!(swing_ticks(0.58, 48, 3), 8);
+        assert_eq!(swing_ticks(2.0 / 3.0, 96, 5), 32);
+    }
+
+    #[test]
+    fn swung_steps_play_late() {
+        let (mut arp, out) = mock_arp();
+        arp.set_step(0, 1).unwrap();
+        arp.swing_idx = SWING_PRESETS.len() - 1;
+        arp.playing = true;
+        // the first step of the pair plays right away
+        arp.flush_notes(0).unwrap();
+        assert_eq!(out.sent().len(), 1);
+        // the second waits a third of the step
+        arp.flush_notes(0).unwrap();
+        let sent = out.sent().len();
+        for _ in 0..15 {
+            arp.scheduler.tick();
+            arp.update().unwrap();
+        }
+        assert_eq!(out.sent().len(), sent);
+        arp.scheduler.tick();
+        arp.update().unwrap();
+        assert_eq!(out.sent().len(), sent + 1);
+    }
+
+    #[test]
+    fn clock_out_sends_24_pulses_per_beat() {
+        let (mut arp, out) = mock_arp();
+        for _ in 0..TICKS_PER_BEAT {
+            arp.clock_tick().unwrap();
+        }
+        assert!(out.sent().is_empty());
+        arp.clock_out = true;
+        for _ in 0..TICKS_PER_BEAT * 2 {
+            arp.clock_tick().unwrap();
+        }
+        let pulses = out.sent().iter().filter(|m| m[0] == CLOCK).count();
+        assert_eq!(pulses, CLOCK_PPQN * 2);
+    }
+
+    #[test]
+    fn clock_out_brackets_playback_with_start_and_stop() {
+        let (mut arp, out) = mock_arp();
+        arp.clock_out = true;
+        arp.play().unwrap();
+        arp.pause().unwrap();
+        let status: Vec<u8> = out.sent().iter().map(|m| m[0]).collect();
+        assert_eq!(status.first(), Some(&START));
+        assert_eq!(status.last(), Some(&STOP));
+    }
+
+    #[test]
+    fn clock_in_steps_in_lockstep_with_pulses() {
+        let (mut arp, out) = mock_arp();
+        arp.clock_in = true;
+        arp.playing = true;
+        // an eighth note step is 12 pulses at 24 per beat
+        for step in 1..=4 {
+            for _ in 0..11 {
+                out.inject([CLOCK, 0, 0, 0]);
+            }
+            arp.check_midi_inputs().unwrap();
+            assert_eq!(arp.cur().index, step - 1);
+            out.inject([CLOCK, 0, 0, 0]);
+            arp.check_midi_inputs().unwrap();
+            assert_eq!(arp.cur().index, step);
+        }
+        assert!(arp.external_clock());
+    }
+
+    #[test]
+    fn clock_in_off_ignores_pulses() {
+        let (mut arp, out) = mock_arp();
+        arp.playing = true;
+        for _ in 0..48 {
+            out.inject([CLOCK, 0, 0, 0]);
+        }
+        arp.check_midi_inputs().unwrap();
+        assert_eq!(arp.cur().index, 0);
+        assert!(!arp.external_clock());
+    }
+
+    /// An arp driving a Launchpad on a mock device, with a clone of
+    /// the grid's device to count LED messages
+    fn launchpad_arp(model: LaunchpadModel) -> (Arp<'static>, MockDevice) {
+        let grid = MockDevice::new();
+        let lp = Launchpad::new(grid.clone(), model);
+        (Arp::new(Box::new(MockDevice::new()), Box::new(lp)), grid)
+    }
+
+    #[test]
+    fn redrawing_an_unchanged_ui_sends_nothing() {
+        for model in [LaunchpadModel::Mk1, LaunchpadModel::Mk2, LaunchpadModel::Mk3] {
+            let (mut arp, grid) = launchpad_arp(model);
+            arp.redraw().unwrap();
+            grid.clear_sent();
+            arp.render_ui().unwrap();
+            assert!(grid.sent().is_empty(), "{:?}", model);
+        }
+    }
+
+    #[test]
+    fn small_changes_send_only_the_changed_leds() {
+        let (mut arp, grid) = launchpad_arp(LaunchpadModel::Mk1);
+        arp.redraw().unwrap();
+        grid.clear_sent();
+        arp.set_step(3, 4).unwrap();
+        arp.flush_leds().unwrap();
+        assert_eq!(grid.sent().len(), 1);
+    }
+
+    #[test]
+    fn large_changes_use_the_cheapest_update() {
+        let (mut arp, grid) = launchpad_arp(LaunchpadModel::Mk1);
+        arp.leds = [led_color(3, 3); FRAME_LEN];
+        arp.flush_leds().unwrap();
+        assert_eq!(grid.sent(), rapid_update(&[led_color(3, 3); FRAME_LEN]));
+
+        let (mut arp, grid) = launchpad_arp(LaunchpadModel::Mk2);
+        arp.leds = [led_color(3, 3); FRAME_LEN];
+        arp.flush_leds().unwrap();
+        assert_eq!(grid.sent().len(), FRAME_LEN);
+    }
+
+    #[test]
+    fn humanize_delays_stay_within_the_step() {
+        let (mut arp, out) = mock_arp();
+        arp.scheduler.set_rate(120, TICKS_PER_BEAT).unwrap();
+        arp.rng = Rng::new(7);
+        arp.humanize = 255;
+        arp.set_step(0, 1).unwrap();
+        arp.playing = true;
+        let step = arp.cur().step_ticks;
+        for _ in 0..50 {
+            out.clear_sent();
+            arp.flush_notes(0).unwrap();
+            for _ in 0..step - 1 {
+                arp.scheduler.tick();
+                arp.update().unwrap();
+            }
+            let ons = out.sent().iter().filter(|m| m[0] & 0xF0 == NOTE).count();
+            assert_eq!(ons, 1);
+            arp.scheduler.tick();
+            arp.update().unwrap();
+        }
+    }
+
+    #[test]
+    fn input_errors_surface_then_clear_on_recovery() {
+        let (mut arp, grid) = launchpad_arp(LaunchpadModel::Mk1);
+        grid.set_failing(true);
+        assert!(arp.check_inputs().is_err());
+        grid.set_failing(false);
+        grid.inject([0x90, 0, 127, 0]);
+        arp.check_inputs().unwrap();
+        assert_eq!(arp.get_step(0), 7);
+
+        let (mut arp, out) = mock_arp();
+        out.set_failing(true);
+        assert!(arp.check_inputs().is_err());
+        out.set_failing(false);
+        arp.check_inputs().unwrap();
+    }
+
+    /// The lane's index after each of `n` steps in a direction
+    fn walk(arp: &mut Arp, direction: Direction, n: usize) -> Vec<usize> {
+        arp.direction = direction;
+        arp.lanes[0].rewind();
+        (0..n)
+            .map(|_| {
+                arp.advance_index(0);
+                arp.cur().index
+            })
+            .collect()
+    }
+
+    #[test]
+    fn directions_walk_one_loop_in_order() {
+        let (mut arp, _) = mock_arp();
+        arp.cur_mut().pattern_len = 4;
+        assert_eq!(walk(&mut arp, Direction::Up, 4), vec![1, 2, 3, 0]);
+        assert_eq!(walk(&mut arp, Direction::Down, 4), vec![3, 2, 1, 0]);
+        assert_eq!(walk(&mut arp, Direction::UpDown, 6), vec![1, 2, 3, 2, 1, 0]);
+    }
+
+    #[test]
+    fn random_direction_only_lands_on_active_columns() {
+        let (mut arp, _) = mock_arp();
+        arp.rng = Rng::new(3);
+        arp.cur_mut().pattern_len = 8;
+        arp.set_step(2, 1).unwrap();
+        arp.set_step(5, 4).unwrap();
+        let steps = walk(&mut arp, Direction::Random, 50);
+        assert!(steps.iter().all(|i| *i == 2 || *i == 5));
+        assert!(steps.contains(&2) && steps.contains(&5));
+    }
+
+    #[test]
+    fn columns_play_at_their_own_velocity() {
+        let (mut arp, out) = mock_arp();
+        arp.set_step(0, 1).unwrap();
+        arp.cur_mut().buffer[0].vel = 42;
+        arp.playing = true;
+        arp.flush_notes(0).unwrap();
+        assert_eq!(out.sent(), vec![[NOTE, 60, 42, 1]]);
+    }
+
+    #[test]
+    fn octave_offsets_shift_a_column_by_octaves() {
+        let (mut arp, _) = mock_arp();
+        arp.set_step(0, 3).unwrap();
+        arp.set_step(1, 3).unwrap();
+        arp.cur_mut().buffer[1].octave_offset = 1;
+        let (a, b) = (arp.cur().buffer[0], arp.cur().buffer[1]);
+        assert_eq!(arp.pitches_of(0, &b)[0], arp.pitches_of(0, &a)[0] + 12);
+        // notes past the top of the MIDI range are skipped, not wrapped
+        arp.cur_mut().octave = 7;
+        arp.cur_mut().buffer[1].octave_offset = 4;
+        let b = arp.cur().buffer[1];
+        assert!(arp.pitches_of(0, &b).is_empty());
+    }
+
+    #[test]
+    fn edits_while_held_leave_the_snapshot_alone() {
+        let (mut arp, _) = mock_arp();
+        arp.set_step(0, 3).unwrap();
+        arp.toggle_hold().unwrap();
+        arp.set_step(0, 5).unwrap();
+        arp.set_step(1, 2).unwrap();
+        assert_eq!(arp.cur().playing_buffer(true)[0].val, 3);
+        assert_eq!(arp.cur().playing_buffer(true)[1].val, 0);
+        // releasing goes back to the live, edited pattern
+        arp.toggle_hold().unwrap();
+        assert_eq!(arp.cur().playing_buffer(arp.hold)[0].val, 5);
+    }
+
+    #[test]
+    fn a_16_step_pattern_wraps_after_its_last_step() {
+        let (mut arp, _) = mock_arp();
+        arp.set_pattern_len(16).unwrap();
+        arp.cur_mut().index = 15;
+        arp.advance_index(0);
+        assert_eq!(arp.cur().index, 0);
+        // the views past the end can't be selected
+        arp.top_row_dispatch(2).unwrap();
+        assert_eq!(arp.buffer_index, 0);
+        arp.top_row_dispatch(1).unwrap();
+        assert_eq!(arp.buffer_index, 1);
+    }
+
+    /// How many note-ons were sent
+    fn note_ons(out: &MockDevice) -> usize {
+        out.sent().iter().filter(|m| m[0] & 0xF0 == NOTE).count()
+    }
+
+    #[test]
+    fn probability_0_never_fires_and_100_always_does() {
+        let (mut arp, out) = mock_arp();
+        arp.rng = Rng::new(11);
+        arp.set_step(0, 1).unwrap();
+        arp.playing = true;
+        arp.cur_mut().buffer[0].prob = 0;
+        for _ in 0..100 {
+            arp.flush_notes(0).unwrap();
+        }
+        assert_eq!(note_ons(&out), 0);
+        arp.cur_mut().buffer[0].prob = 100;
+        for _ in 0..100 {
+            arp.flush_notes(0).unwrap();
+        }
+        assert_eq!(note_ons(&out), 100);
+    }
+
+    #[test]
+    fn ratchet_2_plays_twice_within_the_step() {
+        let (mut arp, out) = mock_arp();
+        arp.set_step(0, 1).unwrap();
+        arp.cur_mut().buffer[0].ratchet = 2;
+        arp.playing = true;
+        arp.flush_notes(0).unwrap();
+        for _ in 0..arp.cur().step_ticks - 1 {
+            arp.scheduler.tick();
+            arp.update().unwrap();
+        }
+        assert_eq!(note_ons(&out), 2);
+        // both repeats are released before the next step starts
+        let offs = out.sent().iter().filter(|m| m[0] & 0xF0 == NOTE_OFF).count();
+        assert_eq!(offs, 2);
+        assert!(arp.cur().active_notes.is_empty());
+    }
+
+    #[test]
+    fn tap_tempo_averages_the_last_four_taps() {
+        let start = Instant::now();
+        let at = |ms: u64| start + Duration::from_millis(ms);
+        assert_eq!(tap_bpm(&[]), None);
+        assert_eq!(tap_bpm(&[at(0)]), None);
+        assert_eq!(tap_bpm(&[at(0), at(0)]), None);
+        assert_eq!(tap_bpm(&[at(0), at(500)]), Some(120.0));
+        // the slow first tap has dropped out of the last four
+        assert_eq!(tap_bpm(&[at(0), at(2000), at(2500), at(3000), at(3500)]), Some(120.0));
+    }
+
+    #[test]
+    fn nudging_the_tempo_retunes_the_scheduler() {
+        let (mut arp, _) = mock_arp();
+        arp.set_bpm(120).unwrap();
+        let minute = Duration::from_secs(60);
+        let ticks = arp.scheduler.ticks_for(minute);
+        arp.shift_held = true;
+        arp.top_row_dispatch(5).unwrap();
+        assert_eq!(arp.bpm, 121);
+        assert!(arp.scheduler.ticks_for(minute) > ticks);
+        arp.top_row_dispatch(4).unwrap();
+        arp.top_row_dispatch(4).unwrap();
+        assert_eq!(arp.bpm, 119);
+        assert!(arp.scheduler.ticks_for(minute) < ticks);
+        // and stops at the ends of the range
+        arp.set_bpm(MAX_BPM).unwrap();
+        arp.top_row_dispatch(5).unwrap();
+        assert_eq!(arp.bpm, MAX_BPM);
+    }
+
+    #[test]
+    fn transport_messages_start_stop_and_continue() {
+        let (mut arp, out) = mock_arp();
+        arp.cur_mut().index = 5;
+        out.inject([START, 0, 0, 0]);
+        arp.check_midi_inputs().unwrap();
+        assert!(arp.playing);
+        assert_eq!(arp.cur().index, 0);
+
+        arp.cur_mut().index = 3;
+        out.inject([STOP, 0, 0, 0]);
+        arp.check_midi_inputs().unwrap();
+        assert!(!arp.playing);
+
+        // continue picks up where playback stopped
+        out.inject([0xFB, 0, 0, 0]);
+        arp.check_midi_inputs().unwrap();
+        assert!(arp.playing);
+        assert_eq!(arp.cur().index, 3);
+    }
+
+    #[test]
+    fn recording_presses_land_under_the_tracker() {
+        let (mut arp, _) = mock_arp();
+        arp.toggle_recording().unwrap();
+        arp.cur_mut().index = 10;
+        arp.grid_button_dispatch(2, 3).unwrap();
+        assert_eq!(arp.get_step(10), 4);
+        assert_eq!(arp.get_step(2), 0);
+    }
+
+    #[test]
+    fn exported_patterns_hold_a_note_pair_per_active_column() {
+        let (mut arp, _) = mock_arp();
+        arp.cur_mut().pattern_len = 4;
+        arp.set_step(0, 1).unwrap();
+        arp.set_step(2, 5).unwrap();
+        let path = std::env::temp_dir().join("lparp-export-test.mid");
+        let path = path.to_str().unwrap();
+        arp.export_smf(path).unwrap();
+        let bytes = std::fs::read(path).unwrap();
+        std::fs::remove_file(path).unwrap();
+
+        assert_eq!(&bytes[..14], b"MThd\0\0\0\x06\0\0\0\x01\0\x60");
+        assert_eq!(&bytes[14..18], b"MTrk");
+        let track = &bytes[22..];
+        let on = |note| track.windows(3).filter(|w| *w == [NOTE, note, 100]).count();
+        assert_eq!((on(60), on(67)), (1, 1));
+        assert_eq!(track.windows(3).filter(|w| w[0] == NOTE_OFF).count(), 2);
+    }
+
+    #[test]
+    fn saved_patterns_load_back_unchanged() {
+        let (mut arp, _) = mock_arp();
+        arp.scale = Scale::Dorian;
+        arp.set_root(7);
+        arp.cur_mut().octave = 3;
+        arp.set_step(0, 1).unwrap();
+        arp.set_step(9, 7).unwrap();
+        arp.cur_mut().buffer[9].vel = 64;
+        arp.cur_mut().buffer[9].octave_offset = -2;
+        arp.cur_mut().buffer[9].prob = 30;
+        arp.cur_mut().buffer[9].ratchet = 3;
+        let path = std::env::temp_dir().join("lparp-save-test.txt");
+        let path = path.to_str().unwrap();
+        arp.save_pattern(path).unwrap();
+
+        let (mut loaded, _) = mock_arp();
+        let res = loaded.load_pattern(path);
+        std::fs::remove_file(path).unwrap();
+        res.unwrap();
+        assert_eq!(loaded.scale.steps(), Scale::Dorian.steps());
+        assert_eq!((loaded.root, loaded.cur().octave), (7, 3));
+        let (a, b) = (arp.cur().buffer[9], loaded.cur().buffer[9]);
+        assert_eq!(
+            (a.val, a.note, a.vel, a.octave_offset, a.prob, a.ratchet),
+            (b.val, b.note, b.vel, b.octave_offset, b.prob, b.ratchet)
+        );
+        assert_eq!(loaded.get_step(0), 1);
+    }
+
+    #[test]
+    fn malformed_patterns_are_rejected_untouched() {
+        let (mut arp, _) = mock_arp();
+        arp.set_step(0, 4).unwrap();
+        let path = std::env::temp_dir().join("lparp-bad-test.txt");
+        let path = path.to_str().unwrap();
+        std::fs::write(path, "root 2\ncol 9 100 0 100 1\n").unwrap();
+        let res = arp.load_pattern(path);
+        std::fs::remove_file(path).unwrap();
+        assert!(res.is_err());
+        assert_eq!((arp.root, arp.get_step(0)), (0, 4));
+        assert!(arp.load_pattern("/nonexistent/lparp.txt").is_err());
+    }
+
+    #[test]
+    fn notes_go_out_on_the_chosen_channel() {
+        let (mut arp, out) = mock_arp();
+        // channels count from 0 here, so MIDI channel 4 is 3
+        arp.set_out_channel(3).unwrap();
+        assert!(arp.set_out_channel(16).is_err());
+        arp.set_step(0, 1).unwrap();
+        arp.playing = true;
+        arp.flush_notes(0).unwrap();
+        assert_eq!(out.sent()[0][0], 0x93);
+    }
+
+    #[test]
+    fn a_release_doesnt_swallow_the_rest_of_the_batch() {
+        let (mut arp, grid) = launchpad_arp(LaunchpadModel::Mk1);
+        grid.inject([0x90, 0, 0, 0]);
+        grid.inject([0x90, 1, 127, 0]);
+        arp.check_inputs().unwrap();
+        assert_eq!(arp.get_step(0), 0);
+        assert_eq!(arp.get_step(1), 7);
+    }
+
+    #[test]
+    fn chord_mode_plays_a_triad_at_once() {
+        let (mut arp, out) = mock_arp();
+        arp.chord = true;
+        arp.set_step(0, 2).unwrap();
+        arp.playing = true;
+        arp.flush_notes(0).unwrap();
+        // D minor in C major: D, F and A together
+        let notes: Vec<u8> = out.sent().iter().map(|m| m[1]).collect();
+        assert_eq!(notes, vec![62, 65, 69]);
+        // notes past 127 are dropped rather than wrapped
+        arp.cur_mut().octave = 7;
+        arp.cur_mut().buffer[0].octave_offset = 3;
+        let col = arp.cur().buffer[0];
+        assert_eq!(arp.pitches_of(0, &col), vec![122, 125]);
+    }
+
+    #[test]
+    fn seeded_random_patterns_repeat() {
+        let vals = |arp: &Arp| (0..MAX_STEPS).map(|c| arp.get_step(c)).collect::<Vec<u8>>();
+        let (mut a, _) = mock_arp();
+        let (mut b, _) = mock_arp();
+        a.rng = Rng::new(5);
+        b.rng = Rng::new(5);
+        a.randomize(50).unwrap();
+        b.randomize(50).unwrap();
+        assert_eq!(vals(&a), vals(&b));
+        assert!(vals(&a).iter().any(|v| *v > 0));
+        // nothing survives a density of 0
+        a.randomize(0).unwrap();
+        assert!(vals(&a).iter().all(|v| *v == 0));
+    }
+
+    #[test]
+    fn muted_rows_stay_quiet_and_solos_play_alone() {
+        let (mut arp, out) = mock_arp();
+        arp.set_step(0, 3).unwrap();
+        arp.set_step(1, 5).unwrap();
+        arp.playing = true;
+        arp.side_mode = SideMode::Mute;
+        arp.side_button_dispatch(4).unwrap();
+        arp.flush_notes(0).unwrap();
+        assert_eq!(note_ons(&out), 0);
+        arp.side_button_dispatch(4).unwrap();
+        arp.flush_notes(0).unwrap();
+        assert_eq!(note_ons(&out), 1);
+
+        // a soloed row silences every other row
+        arp.side_mode = SideMode::Solo;
+        arp.side_button_dispatch(2).unwrap();
+        out.clear_sent();
+        arp.flush_notes(0).unwrap();
+        assert_eq!(note_ons(&out), 0);
+        arp.cur_mut().index = 1;
+        arp.flush_notes(0).unwrap();
+        assert_eq!(note_ons(&out), 1);
+    }
+
+    #[test]
+    fn clearing_a_column_resets_it() {
+        let (mut arp, _) = mock_arp();
+        arp.top_row_dispatch(1).unwrap();
+        arp.grid_button_dispatch(2, 1).unwrap();
+        arp.cur_mut().buffer[10].vel = 30;
+        assert_eq!(arp.get_step(10), 6);
+        assert_eq!(arp.leds[10], arp.grid_io.color(3, 3));
+        // the bottom row clears the column in the current view
+        arp.grid_button_dispatch(2, 7).unwrap();
+        let col = arp.cur().buffer[10];
+        assert_eq!((col.val, col.note, col.vel), (0, 0, 100));
+        assert_eq!(arp.leds[10], 0);
+    }
+
+    #[test]
+    fn transpose_shifts_every_note() {
+        let (mut arp, _) = mock_arp();
+        arp.set_step(0, 1).unwrap();
+        arp.set_step(1, 5).unwrap();
+        let cols = [arp.cur().buffer[0], arp.cur().buffer[1]];
+        let before: Vec<u8> = cols.iter().flat_map(|c| arp.pitches_of(0, c)).collect();
+        arp.shift_held = true;
+        arp.side_button_dispatch(2).unwrap();
+        arp.side_button_dispatch(2).unwrap();
+        let after: Vec<u8> = cols.iter().flat_map(|c| arp.pitches_of(0, c)).collect();
+        assert_eq!(after, before.iter().map(|n| n + 2).collect::<Vec<u8>>());
+
+        // the buttons stop at the limit, and notes pushed past 127 are skipped
+        for _ in 0..30 {
+            arp.side_button_dispatch(2).unwrap();
+        }
+        assert_eq!(arp.transpose, MAX_TRANSPOSE);
+        arp.cur_mut().octave = 7;
+        assert_eq!(arp.pitches_of(0, &cols[0]), vec![84 + MAX_TRANSPOSE as u8]);
+        let top = ArpCol { val: 7, octave_offset: 1, ..cols[0] };
+        assert!(arp.pitches_of(0, &top).is_empty());
+    }
+
+    #[test]
+    fn fader_rows_map_linearly_onto_cc_values() {
+        let (mut arp, out) = mock_arp();
+        arp.cc_num = 74;
+        arp.side_mode = SideMode::Fader;
+        for y in (0..8).rev() {
+            arp.side_button_dispatch(y).unwrap();
+        }
+        let values: Vec<u8> = out.sent().iter().map(|m| m[2]).collect();
+        assert_eq!(values, vec![0, 18, 36, 54, 72, 90, 108, 127]);
+        assert!(out.sent().iter().all(|m| m[0] == MIDI && m[1] == 74));
+    }
+
+    #[test]
+    fn metronome_clicks_once_a_beat_with_an_accented_downbeat() {
+        let (mut arp, out) = mock_arp();
+        arp.metronome = true;
+        arp.scheduler.interval(TICKS_PER_BEAT as usize, Msg::Click);
+        // it sounds whether or not the pattern is playing
+        for _ in 0..TICKS_PER_BEAT as usize * 5 + CLICK_TICKS {
+            arp.scheduler.tick();
+            arp.update().unwrap();
+        }
+        let clicks: Vec<u8> = out
+            .sent()
+            .iter()
+            .filter(|m| m[0] == NOTE | 9 && m[1] == 37)
+            .map(|m| m[2])
+            .collect();
+        assert_eq!(clicks, vec![127, 70, 70, 70, 127]);
+        let offs = out.sent().iter().filter(|m| m[0] == NOTE_OFF | 9).count();
+        assert_eq!(offs, 5);
+    }
+
+    #[test]
+    fn bend_strip_is_centred_on_its_fourth_row() {
+        assert_eq!(Arp::bend_value(BEND_CENTER), 8192);
+        assert_eq!(Arp::bend_value(0), 16383);
+        assert_eq!(Arp::bend_value(7), 0);
+        assert_eq!(Arp::bend_value(20), 0);
+        for y in 0..7 {
+            assert!(Arp::bend_value(y) > Arp::bend_value(y + 1));
+        }
+    }
+
+    #[test]
+    fn leaving_bend_mode_recentres_the_bend() {
+        let (mut arp, out) = mock_arp();
+        arp.side_mode = SideMode::Bend;
+        arp.side_button_dispatch(1).unwrap();
+        out.clear_sent();
+        arp.cycle_side_mode().unwrap();
+        let centre = MidiMessage::PitchBend { channel: 0, value: 8192 };
+        assert_eq!(out.sent(), vec![centre.to_bytes()]);
+        assert_eq!(arp.bend_row, BEND_CENTER);
+        // other modes have nothing to let go of
+        out.clear_sent();
+        arp.cycle_side_mode().unwrap();
+        assert!(out.sent().is_empty());
+    }
+
+    #[test]
+    fn config_settings_carry_over_to_the_arp() {
+        let config = ArpConfig {
+            out_channel: 5,
+            bpm: 90,
+            octave: 3,
+            scale: Scale::Dorian,
+            pattern_len: 12,
+            ..ArpConfig::default()
+        };
+        let grid = Box::new(MockGrid::new());
+        let arp = Arp::with_config(Box::new(MockDevice::new()), grid, &config).unwrap();
+        assert_eq!(arp.bpm, 90);
+        assert_eq!(arp.scale, Scale::Dorian);
+        let lane = arp.cur();
+        assert_eq!((lane.channel, lane.octave, lane.pattern_len), (5, 3, 12));
+        assert_eq!(arp.octave_btn[1], (4 * 16) + 8);
+
+        // out of range values are clamped or refused
+        let config = ArpConfig { bpm: 1000, pattern_len: 99, ..ArpConfig::default() };
+        let grid = Box::new(MockGrid::new());
+        let arp = Arp::with_config(Box::new(MockDevice::new()), grid, &config).unwrap();
+        assert_eq!((arp.bpm, arp.cur().pattern_len), (MAX_BPM, MAX_STEPS));
+        let config = ArpConfig { out_channel: 16, ..ArpConfig::default() };
+        let grid = Box::new(MockGrid::new());
+        assert!(Arp::with_config(Box::new(MockDevice::new()), grid, &config).is_err());
+    }
+
+    #[test]
+    fn clear_board_resets_an_mk1() {
+        let (mut arp, grid) = launchpad_arp(LaunchpadModel::Mk1);
+        arp.clear_board().unwrap();
+        assert_eq!(grid.sent(), vec![[176, 0, 0, 0]]);
+    }
+
+    #[test]
+    fn panic_button_sends_all_notes_off() {
+        let (mut arp, out) = mock_arp();
+        arp.add_lane(4, 48, 5).unwrap();
+        arp.note_on(0, 60, 100).unwrap();
+        out.clear_sent();
+        arp.shift_held = true;
+        arp.top_row_dispatch(7).unwrap();
+        assert_eq!(
+            out.sent(),
+            vec![[NOTE_OFF, 60, 0, 0], [MIDI, 123, 0, 0], [MIDI | 4, 123, 0, 0]]
+        );
+        assert!(arp.running);
+    }
+
+    #[test]
+    fn pausing_releases_exactly_the_sounding_notes() {
+        let (mut arp, out) = mock_arp();
+        arp.play().unwrap();
+        arp.note_on(0, 60, 100).unwrap();
+        arp.note_on(0, 64, 100).unwrap();
+        arp.note_on(0, 67, 100).unwrap();
+        arp.note_off(0, 64).unwrap();
+        out.clear_sent();
+        arp.pause().unwrap();
+        assert_eq!(out.sent(), vec![[NOTE_OFF, 60, 0, 0], [NOTE_OFF, 67, 0, 0]]);
+        assert!(arp.cur().active_notes.is_empty());
+    }
+
+    #[test]
+    fn lanes_advance_independently() {
+        let (mut arp, _) = mock_arp();
+        arp.cur_mut().pattern_len = 3;
+        arp.add_lane(1, Subdivision::Quarter.ticks(), 5).unwrap();
+        arp.lanes[1].pattern_len = 5;
+        arp.schedule_lanes();
+        arp.playing = true;
+        for _ in 0..TICKS_PER_BEAT * 4 {
+            arp.scheduler.tick();
+            arp.update().unwrap();
+        }
+        // eight eighths wrap a 3-step lane, four quarters don't wrap a 5-step one
+        assert_eq!(arp.lanes[0].index, 8 % 3);
+        assert_eq!(arp.lanes[1].index, 4);
+    }
+
+    #[cfg(feature = "osc")]
+    #[test]
+    fn osc_input_drives_the_arp() {
+        let (mut arp, _) = mock_arp();
+        let listener = OscListener::bind("127.0.0.1:0").unwrap();
+        let sender = OscSender::new(listener.local_addr()).unwrap();
+        arp.osc_in = Some(listener);
+        sender.send("/bpm", &[OscArg::Int(90)]).unwrap();
+        sender.send("/bpm", &[OscArg::Float(f32::NAN)]).unwrap();
+        sender.send("/scale", &[OscArg::Float(1e30)]).unwrap();
+        sender.send("/scale", &[OscArg::Int(SCALE_COUNT as i32 + 2)]).unwrap();
+        sender.send("/scale", &[OscArg::Float(f32::INFINITY)]).unwrap();
+        sender.send("/play", &[]).unwrap();
+
+        let started = Instant::now();
+        while !arp.playing && started.elapsed() < Duration::from_secs(2) {
+            arp.check_osc_inputs().unwrap();
+            std::thread::sleep(Duration::from_millis(5));
+        }
+        assert!(arp.playing);
+        assert_eq!(arp.bpm, 90);
+        // values past the end wrap around the cycle instead of walking it
+        assert_eq!(arp.scale, Scale::Phrygian);
+    }
+
+    #[test]
+    fn idle_inputs_are_never_read() {
+        let grid = MockDevice::new();
+        let out = MockDevice::new();
+        let lp = Launchpad::new(grid.clone(), LaunchpadModel::Mk1);
+        let mut arp = Arp::new(Box::new(out.clone()), Box::new(lp));
+        for _ in 0..10 {
+            arp.check_inputs().unwrap();
+        }
+        assert_eq!((grid.reads(), out.reads()), (0, 0));
+        grid.inject([0x90, 0, 127, 0]);
+        out.inject([CLOCK, 0, 0, 0]);
+        arp.check_inputs().unwrap();
+        assert_eq!((grid.reads(), out.reads()), (1, 1));
+    }
+
+    #[test]
+    fn grid_polls_reuse_one_buffer() {
+        let (mut arp, grid) = launchpad_arp(LaunchpadModel::Mk1);
+        let buf = arp.grid_events.as_ptr();
+        for x in 0..8 {
+            grid.inject([0x90, x, 127, 0]);
+            grid.inject([0x90, x, 0, 0]);
+            arp.check_inputs().unwrap();
+        }
+        assert_eq!(arp.grid_events.as_ptr(), buf);
+        assert!((0..8).all(|col| arp.get_step(col) == 7));
+    }
+
+    #[test]
+    fn steps_set_read_back_and_reject_bad_input() {
+        let (mut arp, _) = mock_arp();
+        arp.set_step(3, 5).unwrap();
+        assert_eq!(arp.get_step(3), 5);
+        assert_eq!(arp.get_step(4), 0);
+        arp.set_step(3, 0).unwrap();
+        assert_eq!(arp.get_step(3), 0);
+
+        assert!(arp.set_step(0, 8).is_err());
+        arp.set_pattern_len(8).unwrap();
+        assert!(arp.set_step(8, 1).is_err());
+        // reads past the end are rests rather than errors
+        assert_eq!(arp.get_step(8), 0);
+        assert_eq!(arp.get_step(0), 0);
+    }
+
+    /// The values of the selected lane's columns, up to its length
+    fn steps(arp: &Arp) -> Vec<u8> {
+        (0..arp.cur().pattern_len).map(|col| arp.get_step(col)).collect()
+    }
+
+    #[test]
+    fn reversing_and_inverting_flip_the_pattern() {
+        let (mut arp, _) = mock_arp();
+        arp.set_pattern_len(8).unwrap();
+        for (col, val) in [1, 2, 0, 4, 7, 0, 0, 3].iter().enumerate() {
+            arp.set_step(col, *val).unwrap();
+        }
+        arp.reverse().unwrap();
+        assert_eq!(steps(&arp), vec![3, 0, 0, 7, 4, 0, 2, 1]);
+        arp.invert_values().unwrap();
+        assert_eq!(steps(&arp), vec![5, 0, 0, 1, 4, 0, 6, 7]);
+        // the grid notes follow the moved values
+        assert_eq!(arp.cur().buffer[0].note, (7 - 5) * 16);
+    }
+
+    #[test]
+    fn rotating_wraps_around_the_pattern_length() {
+        let (mut arp, _) = mock_arp();
+        arp.set_pattern_len(8).unwrap();
+        for (col, val) in [1, 2, 3, 4, 5, 6, 7, 0].iter().enumerate() {
+            arp.set_step(col, *val).unwrap();
+        }
+        arp.rotate(1).unwrap();
+        assert_eq!(steps(&arp), vec![0, 1, 2, 3, 4, 5, 6, 7]);
+        arp.rotate(-1).unwrap();
+        assert_eq!(steps(&arp), vec![1, 2, 3, 4, 5, 6, 7, 0]);
+        // 8 * 3 + 2 comes round to a rotation by 2
+        arp.rotate(26).unwrap();
+        assert_eq!(steps(&arp), vec![7, 0, 1, 2, 3, 4, 5, 6]);
+        arp.rotate(-26).unwrap();
+        assert_eq!(steps(&arp), vec![1, 2, 3, 4, 5, 6, 7, 0]);
+    }
+
+    #[test]
+    fn copying_a_view_duplicates_its_columns() {
+        let (mut arp, _) = mock_arp();
+        for col in 0..8 {
+            arp.set_step(col, (col % 7) as u8 + 1).unwrap();
+            arp.cur_mut().buffer[col].vel = 10 * col as u8;
+        }
+        arp.copy_view(0, 3).unwrap();
+        for col in 0..8 {
+            let (src, dst) = (arp.cur().buffer[col], arp.cur().buffer[24 + col]);
+            assert_eq!((dst.val, dst.vel), (src.val, src.vel));
+            // the copies sit on the same grid pads as the originals
+            assert_eq!(dst.note, src.note);
+        }
+        // the views in between are untouched
+        assert!(steps(&arp)[8..24].iter().all(|v| *v == 0));
+    }
+
+    #[test]
+    fn accented_steps_play_louder() {
+        let (mut arp, out) = mock_arp();
+        arp.set_step(0, 1).unwrap();
+        arp.set_step(1, 1).unwrap();
+        arp.toggle_accent(1).unwrap();
+        arp.playing = true;
+        for index in 0..2 {
+            arp.cur_mut().index = index;
+            arp.flush_notes(0).unwrap();
+        }
+        let vels: Vec<u8> = out.sent().iter().filter(|m| m[0] & 0xF0 == NOTE && m[2] > 0).map(|m| m[2]).collect();
+        assert_eq!(vels.len(), 2);
+        assert!(vels[1] > vels[0]);
+        assert_eq!(vels[1], ACCENT_VEL);
+    }
+
+    #[test]
+    fn held_steps_flash_the_pad_that_plays() {
+        let (mut arp, _) = mock_arp();
+        arp.set_step(0, 3).unwrap();
+        arp.toggle_hold().unwrap();
+        arp.set_step(0, 5).unwrap();
+        let held = arp.cur().playing_buffer(true)[0].note;
+        let live = arp.cur().buffer[0].note;
+        let slot = |note: u8| ((note / 16) * 8 + note % 16) as usize;
+        arp.playing = true;
+        arp.flush_notes(0).unwrap();
+        arp.playing = false;
+        assert_eq!(arp.leds[slot(held)], arp.grid_io.color(0, 3));
+        assert_eq!(arp.leds[slot(live)], arp.grid_io.color(3, 3));
+        // once the flash is over the snapshot's pad is dark again,
+        // since the grid shows the edited column
+        for _ in 0..FLASH_TICKS {
+            arp.scheduler.tick();
+            arp.update().unwrap();
+        }
+        assert_eq!(arp.leds[slot(held)], 0);
+        assert_eq!(arp.leds[slot(live)], arp.grid_io.color(3, 3));
+    }
+
+    #[test]
+    fn restarting_goes_back_to_the_first_beat() {
+        let (mut arp, _) = mock_arp();
+        arp.set_pattern_len(16).unwrap();
+        arp.cur_mut().step_ticks = TICKS_PER_BEAT as usize / 4;
+        arp.playing = true;
+        // five 16th steps in: the second beat of the first bar
+        for _ in 0..5 {
+            arp.flush_notes(0).unwrap();
+        }
+        assert_eq!(arp.position(), (0, 1, 0));
+        for _ in 0..16 {
+            arp.flush_notes(0).unwrap();
+        }
+        assert_eq!(arp.position(), (1, 1, 0));
+        arp.click().unwrap();
+        arp.click().unwrap();
+        assert_eq!(arp.click_beat, 2);
+
+        arp.restart().unwrap();
+        assert_eq!(arp.click_beat, 0);
+        arp.flush_notes(0).unwrap();
+        assert_eq!(arp.position(), (0, 0, 0));
+    }
+
+    #[test]
+    fn gates_always_leave_room_for_the_release() {
+        assert_eq!(gate_ticks(1.0, 48), 47);
+        assert_eq!(gate_ticks(0.5, 48), 24);
+        assert_eq!(gate_ticks(0.0, 48), 1);
+        assert_eq!(gate_ticks(2.0, 48), 47);
+        assert_eq!(gate_ticks(1.0, 1), 1);
+        assert_eq!(gate_ticks(1.0, 0), 1);
+    }
+
+    #[test]
+    fn random_gates_stay_within_their_bounds() {
+        let (mut arp, _) = mock_arp();
+        arp.rng = Rng::new(93);
+        (arp.gate_min, arp.gate_max) = (0.25, 0.75);
+        let gates: Vec<f32> = (0..200).map(|_| arp.pick_gate()).collect();
+        assert!(gates.iter().all(|g| (0.25..=0.75).contains(g)));
+        // and they do vary
+        assert!(gates.iter().any(|g| *g != gates[0]));
+        // bounds given the wrong way round are swapped
+        (arp.gate_min, arp.gate_max) = (0.75, 0.25);
+        assert!((0..200).map(|_| arp.pick_gate()).all(|g| (0.25..=0.75).contains(&g)));
+        (arp.gate_min, arp.gate_max) = (0.5, 0.5);
+        assert_eq!(arp.pick_gate(), 0.5);
+    }
+
+    #[test]
+    fn stutter_repeats_only_the_selected_lane_while_held() {
+        let (mut arp, out) = mock_arp();
+        arp.set_step(0, 1).unwrap();
+        arp.add_lane(1, arp.cur().step_ticks, 5).unwrap();
+        arp.lanes[1].buffer[0].val = 1;
+        arp.playing = true;
+        // note-ons per channel over one step of both lanes
+        let step = |arp: &mut Arp| {
+            out.clear_sent();
+            arp.flush_notes(0).unwrap();
+            arp.flush_notes(1).unwrap();
+            for _ in 0..arp.cur().step_ticks - 1 {
+                arp.scheduler.tick();
+                arp.update().unwrap();
+            }
+            let ons = out.sent().into_iter().filter(|m| m[0] & 0xF0 == NOTE && m[2] > 0);
+            ons.fold([0, 0], |mut n, m| {
+                n[(m[0] & 0x0F) as usize] += 1;
+                n
+            })
+        };
+        assert_eq!(step(&mut arp), [1, 1]);
+        arp.top_row_dispatch(5).unwrap();
+        assert_eq!(step(&mut arp), [STUTTER_REPEATS, 1]);
+        arp.top_row_release(5).unwrap();
+        assert_eq!(step(&mut arp), [1, 1]);
+    }
+
+    #[test]
+    fn tempo_bar_spans_the_bpm_range() {
+        assert_eq!(Arp::tempo_level(MIN_BPM), 1);
+        assert_eq!(Arp::tempo_level(10), 1);
+        assert_eq!(Arp::tempo_level(MAX_BPM), 8);
+        assert_eq!(Arp::tempo_level(1000), 8);
+        for bpm in MIN_BPM..MAX_BPM {
+            assert!(Arp::tempo_level(bpm + 1) >= Arp::tempo_level(bpm));
+        }
+        // every LED covers the same share of the range, give or take one
+        for level in 1..=8 {
+            let n = (MIN_BPM..=MAX_BPM).filter(|bpm| Arp::tempo_level(*bpm) == level).count();
+            assert!((32..=33).contains(&n), "level {} covers {} tempos", level, n);
+        }
+    }
+
+    #[test]
+    fn shift_and_a_pad_set_the_column_velocity() {
+        let (mut arp, _) = mock_arp();
+        arp.set_step(2, 4).unwrap();
+        let scale = arp.scale.clone();
+        arp.top_row_dispatch(6).unwrap();
+        arp.grid_button_dispatch(2, 1).unwrap();
+        arp.top_row_release(6).unwrap();
+        assert_eq!(arp.cur().buffer[2].vel, 127 - 16);
+        // the press doesn't touch the step, and the shift key,
+        // having been used, doesn't cycle the scale on release
+        assert_eq!(arp.get_step(2), 4);
+        assert_eq!(arp.scale, scale);
+    }
+
+    #[test]
+    fn subdivisions() {
+        let subs = [
+            (Subdivision::Quarter, 96, "quarter"),
+            (Subdivision::Eighth, 48, "eighth"),
+            (Subdivision::EighthTriplet, 32, "triplet"),
+            (Subdivision::Sixteenth, 24, "sixteenth"),
+        ];
+        for (i, (sub, ticks, name)) in subs.iter().enumerate() {
+            assert_eq!(sub.ticks(), *ticks);
+            assert_eq!(Subdivision::from_ticks(*ticks).map(|s| s.ticks()), Some(*ticks));
+            assert_eq!(Subdivision::from_name(name).map(|s| s.ticks()), Some(*ticks));
+            assert_eq!(sub.next().ticks(), subs[(i + 1) % subs.len()].0.ticks());
+        }
+        assert!(Subdivision::from_ticks(40).is_none());
+        assert!(Subdivision::from_name("whole").is_none());
+    }
+
+    #[test]
+    fn lane_steps_are_names_or_64ths_of_a_quarter() {
+        assert_eq!(lane_step("triplet"), Some(Subdivision::EighthTriplet.ticks()));
+        assert_eq!(lane_step("64"), Some(TICKS_PER_BEAT as usize));
+        assert_eq!(lane_step("32"), Some(Subdivision::Eighth.ticks()));
+        assert_eq!(lane_step("1"), Some(2));
+        assert_eq!(lane_step("0"), None);
+        assert_eq!(lane_step("half"), None);
+    }
+
+    #[test]
+    fn past_the_voice_limit_the_oldest_note_is_stolen() {
+        let (mut arp, out) = mock_arp();
+        arp.max_voices = 2;
+        arp.note_on(0, 60, 100).unwrap();
+        arp.note_on(0, 64, 100).unwrap();
+        // playing 60 again makes 64 the oldest
+        arp.note_on(0, 60, 100).unwrap();
+        out.clear_sent();
+        arp.note_on(0, 67, 100).unwrap();
+        assert_eq!(out.sent(), vec![[NOTE_OFF, 64, 0, 0], [NOTE, 67, 100, 1]]);
+        assert_eq!(arp.cur().active_notes, vec![60, 67]);
+    }
+
+    #[test]
+    fn chains_play_their_views_in_order() {
+        let (mut arp, _) = mock_arp();
+        arp.set_chain(vec![0, 0, 1]).unwrap();
+        let mut played = vec![arp.cur().index];
+        played.extend(walk(&mut arp, Direction::Up, 25));
+        // and then back round to the start of the chain
+        let expected: Vec<usize> = (0..8).chain(0..8).chain(8..16).chain(0..2).collect();
+        assert_eq!(played, expected);
+    }
+
+    #[test]
+    fn shortening_the_pattern_trims_the_chain() {
+        let (mut arp, _) = mock_arp();
+        assert!(arp.set_chain(vec![0, 4]).is_err());
+        arp.set_chain(vec![0, 1, 3]).unwrap();
+        // the last view is cut short by a 28-step pattern...
+        arp.set_pattern_len(28).unwrap();
+        arp.cur_mut().chain_pos = 2;
+        assert_eq!(arp.cur().segment(), (24, 4));
+        // ...and dropped altogether by a 12-step one
+        arp.set_pattern_len(12).unwrap();
+        assert_eq!(arp.cur().chain, vec![0, 1]);
+        assert_eq!((arp.cur().chain_pos, arp.cur().index), (0, 0));
+        arp.cur_mut().chain_pos = 1;
+        assert_eq!(arp.cur().segment(), (8, 4));
+    }
+}
+
 // end lparp.rs
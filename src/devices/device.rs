@@ -1,59 +1,371 @@
 // device.rs - a MIDI device abstraction
 
 extern crate portmidi as pm;
+use crate::types::{MidiError, MidiRes};
+
+/// Default PortMidi event buffer size for both input and output ports
+pub const DEFAULT_BUFFER_SIZE: usize = 1024;
+
+/// The raw MIDI I/O a program needs from a device. Device is the
+/// PortMidi-backed implementation, MockDevice a hardware-free one.
+pub trait MidiIo {
+    /// Write a raw message
+    fn write_message(&mut self, msg: [u8; 4]) -> MidiRes;
+
+    /// Write a system exclusive message, 0xF0 through 0xF7
+    fn write_sysex(&mut self, msg: &[u8]) -> MidiRes;
+
+    /// Read up to `cnt` pending input events
+    fn read_n(&self, cnt: usize) -> Result<Option<Vec<pm::MidiEvent>>, MidiError>;
+
+    /// Read up to `cnt` pending input events into `buf`, replacing
+    /// its contents, and return how many were read. Reusing one buffer
+    /// avoids an allocation per read once it has grown to size.
+    fn read_into(&mut self, buf: &mut Vec<pm::MidiEvent>, cnt: usize) -> Result<usize, MidiError> {
+        buf.clear();
+        if let Some(evts) = self.read_n(cnt)? {
+            buf.extend(evts);
+        }
+        Ok(buf.len())
+    }
+
+    /// Whether any input events are waiting, so callers can skip
+    /// reading when there's nothing there. Defaults to true, which
+    /// leaves it to `read_n()` to find out.
+    fn has_input(&self) -> bool {
+        true
+    }
+
+    /// Switch the patch on a channel (0-15) to a program (0-127)
+    /// Fails with OutOfRange for a channel past 15 or a program past 127
+    fn program_change(&mut self, channel: u8, program: u8) -> MidiRes {
+        if channel > 15 {
+            return Err(MidiError::OutOfRange("channel", channel));
+        }
+        if program > 127 {
+            return Err(MidiError::OutOfRange("program", program));
+        }
+        self.write_message([0xC0 | channel, program, 0, 0])
+    }
+
+    /// Silence everything downstream: release the sustain pedal
+    /// and send All Notes Off (CC 123) on all 16 channels
+    fn panic(&mut self) -> MidiRes {
+        for channel in 0..16 {
+            self.write_message([0xB0 | channel, 64, 0, 0])?;
+            self.write_message([0xB0 | channel, 123, 0, 0])?;
+        }
+        Ok(())
+    }
+
+    /// Drain and discard any pending input events
+    fn flush_input(&mut self) -> MidiRes {
+        while let Some(evts) = self.read_n(DEFAULT_BUFFER_SIZE)? {
+            if evts.is_empty() {
+                break;
+            }
+        }
+        Ok(())
+    }
+}
 
 /// The Device is an abstraction for generic MIDI read/write purposes.
 /// You can implement any kind of Device abstraction using this as the
 /// main source of I/O passthrough. Including it and some info about
 /// the device enables you to create simple APIs for devices.
+/// Either port may be missing for input-only or output-only devices.
 pub struct Device<'a> {
-    pub input: pm::InputPort<'a>,
-    pub output: pm::OutputPort<'a>,
+    pub input: Option<pm::InputPort<'a>>,
+    pub output: Option<pm::OutputPort<'a>>,
 }
 
 impl Device<'_> {
-    pub fn new<'a>(name: &'a str, ctx: &'a pm::PortMidi) -> Result<Device<'a>, String> {
+    /// Query every MIDI port PortMidi knows about
+    /// Each entry is (id, name, is_input, is_output)
+    fn query_devices(ctx: &pm::PortMidi) -> Result<Vec<(i32, String, bool, bool)>, MidiError> {
+        Ok(ctx
+            .devices()?
+            .iter()
+            .map(|dev| (dev.id(), dev.name().clone(), dev.is_input(), dev.is_output()))
+            .collect())
+    }
+
+    /// List the available MIDI ports as (id, name, is_input, is_output)
+    /// Returns an empty list if the ports could not be queried
+    pub fn list_devices(ctx: &pm::PortMidi) -> Vec<(i32, String, bool, bool)> {
+        Self::query_devices(ctx).unwrap_or_default()
+    }
+
+    /// Find the (input, output) port ids matching a device name
+    /// When verbose, every port seen is printed to stdout
+    fn find_ids(
+        name: &str,
+        ctx: &pm::PortMidi,
+        verbose: bool,
+    ) -> Result<(Option<i32>, Option<i32>), MidiError> {
         let mut output_id: Option<i32> = None;
         let mut input_id: Option<i32> = None;
 
-        for dev in ctx.devices().expect("Failed to query devices") {
-            println!("Device: {}, id: {}", dev.name(), dev.id());
-            if dev.name() == name {
-                if dev.is_output() {
-                    output_id = Some(dev.id());
+        for (id, dev_name, is_input, is_output) in Self::query_devices(ctx)? {
+            if verbose {
+                println!("Device: {}, id: {}", dev_name, id);
+            }
+            if dev_name == name {
+                if is_output {
+                    output_id = Some(id);
                 }
 
-                if dev.is_input() {
-                    input_id = Some(dev.id());
+                if is_input {
+                    input_id = Some(id);
                 }
             }
         }
+        Ok((input_id, output_id))
+    }
+
+    /// Open an input port by id
+    fn open_input(id: i32, ctx: &pm::PortMidi, buf_size: usize) -> Result<pm::InputPort<'_>, MidiError> {
+        let in_port = ctx.device(id)?;
+        Ok(ctx.input_port(in_port, buf_size)?)
+    }
+
+    /// Open an output port by id
+    fn open_output(id: i32, ctx: &pm::PortMidi, buf_size: usize) -> Result<pm::OutputPort<'_>, MidiError> {
+        let out_port = ctx.device(id)?;
+        Ok(ctx.output_port(out_port, buf_size)?)
+    }
+
+    /// Open a device with both an input and an output port
+    pub fn new<'a>(name: &str, ctx: &'a pm::PortMidi) -> Result<Device<'a>, MidiError> {
+        Self::open(name, ctx, DEFAULT_BUFFER_SIZE, false)
+    }
+
+    /// Same as `new()`, but prints every MIDI port found while searching
+    pub fn new_verbose<'a>(name: &str, ctx: &'a pm::PortMidi) -> Result<Device<'a>, MidiError> {
+        Self::open(name, ctx, DEFAULT_BUFFER_SIZE, true)
+    }
+
+    /// Same as `new()`, but with a custom PortMidi event buffer size
+    /// for both ports. Larger buffers can absorb dense bursts of
+    /// events without overflowing and dropping them, at the cost of
+    /// more memory and a longer backlog (latency) when the reader
+    /// falls behind. Smaller buffers keep latency low but overflow
+    /// sooner. The default is 1024 events.
+    pub fn with_buffer_size<'a>(
+        name: &str,
+        ctx: &'a pm::PortMidi,
+        buf_size: usize,
+    ) -> Result<Device<'a>, MidiError> {
+        Self::open(name, ctx, buf_size, false)
+    }
+
+    /// Fails with DeviceNotFound when no port has the name, or
+    /// NoInput/NoOutput when only one direction does
+    fn open<'a>(
+        name: &str,
+        ctx: &'a pm::PortMidi,
+        buf_size: usize,
+        verbose: bool,
+    ) -> Result<Device<'a>, MidiError> {
+        match Self::find_ids(name, ctx, verbose)? {
+            (Some(iid), Some(oid)) => Ok(Device {
+                input: Some(Self::open_input(iid, ctx, buf_size)?),
+                output: Some(Self::open_output(oid, ctx, buf_size)?),
+            }),
+            (None, None) => Err(MidiError::DeviceNotFound(name.into())),
+            (_, None) => Err(MidiError::NoOutput),
+            _ => Err(MidiError::NoInput),
+        }
+    }
+
+    /// Open a device from exact port ids, e.g. ones found with
+    /// `list_devices()`. Useful when several ports share a name.
+    pub fn from_ids(input_id: i32, output_id: i32, ctx: &pm::PortMidi) -> Result<Device<'_>, MidiError> {
+        Ok(Device {
+            input: Some(Self::open_input(input_id, ctx, DEFAULT_BUFFER_SIZE)?),
+            output: Some(Self::open_output(output_id, ctx, DEFAULT_BUFFER_SIZE)?),
+        })
+    }
+
+    /// Open a device that only sends MIDI out
+    pub fn output_only<'a>(name: &str, ctx: &'a pm::PortMidi) -> Result<Device<'a>, MidiError> {
+        match Self::find_ids(name, ctx, false)? {
+            (_, Some(oid)) => Ok(Device {
+                input: None,
+                output: Some(Self::open_output(oid, ctx, DEFAULT_BUFFER_SIZE)?),
+            }),
+            _ => Err(MidiError::DeviceNotFound(name.into())),
+        }
+    }
+
+    /// Open a device that only receives MIDI in
+    pub fn input_only<'a>(name: &str, ctx: &'a pm::PortMidi) -> Result<Device<'a>, MidiError> {
+        match Self::find_ids(name, ctx, false)? {
+            (Some(iid), _) => Ok(Device {
+                input: Some(Self::open_input(iid, ctx, DEFAULT_BUFFER_SIZE)?),
+                output: None,
+            }),
+            _ => Err(MidiError::DeviceNotFound(name.into())),
+        }
+    }
 
-        match (output_id, input_id) {
-            (Some(oid), Some(iid)) => {
-                let out_port = ctx
-                    .device(oid)
-                    .expect("Failed to find matching output device");
-
-                let in_port = ctx
-                    .device(iid)
-                    .expect("Failed to find matching input device");
-
-                Ok(Device {
-                    input: ctx
-                        .input_port(in_port, 1024)
-                        .expect("Failed to open input port"),
-                    output: ctx
-                        .output_port(out_port, 1024)
-                        .expect("Failed to open output port"),
-                })
+    /// Write a raw message to the output port
+    /// Fails with NoOutput if the device has no output
+    pub fn write_message(&mut self, msg: [u8; 4]) -> MidiRes {
+        match self.output.as_mut() {
+            Some(output) => Ok(output.write_message(msg)?),
+            None => Err(MidiError::NoOutput),
+        }
+    }
+
+    /// Write a system exclusive message to the output port
+    /// The message must start with 0xF0 and end with 0xF7
+    pub fn write_sysex(&mut self, msg: &[u8]) -> MidiRes {
+        match self.output.as_mut() {
+            Some(output) => Ok(output.write_sysex(0, msg)?),
+            None => Err(MidiError::NoOutput),
+        }
+    }
+
+    /// Read up to `cnt` events from the input port
+    /// Fails with NoInput if the device has no input
+    pub fn read_n(&self, cnt: usize) -> Result<Option<Vec<pm::MidiEvent>>, MidiError> {
+        match self.input.as_ref() {
+            Some(input) => Ok(input.read_n(cnt)?),
+            None => Err(MidiError::NoInput),
+        }
+    }
+
+    /// Same as `read_n()`, but fills a caller-owned buffer one event
+    /// at a time instead of allocating a new one
+    pub fn read_into(&mut self, buf: &mut Vec<pm::MidiEvent>, cnt: usize) -> Result<usize, MidiError> {
+        buf.clear();
+        let input = self.input.as_mut().ok_or(MidiError::NoInput)?;
+        while buf.len() < cnt {
+            match input.read()? {
+                Some(e) => buf.push(e),
+                None => break,
+            }
+        }
+        Ok(buf.len())
+    }
+
+    /// Whether events are waiting on the input port, without reading
+    /// them. A failed poll counts as waiting so `read_n()` can report
+    /// the error, and a device without an input never has any.
+    pub fn has_input(&self) -> bool {
+        match self.input.as_ref() {
+            Some(input) => input.poll().unwrap_or(true),
+            None => false,
+        }
+    }
+
+    /// Drain and discard any events already waiting on the input port,
+    /// e.g. button presses buffered before the program started.
+    /// Events are swallowed, never dispatched anywhere.
+    pub fn flush_input(&mut self) -> MidiRes {
+        if let Some(input) = self.input.as_ref() {
+            while let Some(evts) = input.read_n(DEFAULT_BUFFER_SIZE)? {
+                if evts.is_empty() {
+                    break;
+                }
             }
-            _ => Err("Failed to create a device context".into()),
         }
+        Ok(())
+    }
+
+    /// Forward every pending input event to another device's output,
+    /// byte for byte. Reading consumes the events from this device,
+    /// so they are handed back to the caller for any further processing.
+    pub fn thru(&mut self, dest: &mut Device) -> Result<Vec<pm::MidiEvent>, MidiError> {
+        let evts = self.read_n(DEFAULT_BUFFER_SIZE)?.unwrap_or_default();
+        for e in evts.iter() {
+            let m = e.message;
+            dest.write_message([m.status, m.data1, m.data2, m.data3])?;
+        }
+        Ok(evts)
+    }
+
+    /// Write a message from its individual bytes
+    pub fn write(&mut self, kind: u8, note: u8, vel: u8, extra: u8) -> MidiRes {
+        self.write_message([kind, note, vel, extra])
+    }
+}
+
+impl MidiIo for Device<'_> {
+    fn write_message(&mut self, msg: [u8; 4]) -> MidiRes {
+        Device::write_message(self, msg)
+    }
+
+    fn write_sysex(&mut self, msg: &[u8]) -> MidiRes {
+        Device::write_sysex(self, msg)
+    }
+
+    fn read_n(&self, cnt: usize) -> Result<Option<Vec<pm::MidiEvent>>, MidiError> {
+        Device::read_n(self, cnt)
+    }
+
+    fn read_into(&mut self, buf: &mut Vec<pm::MidiEvent>, cnt: usize) -> Result<usize, MidiError> {
+        Device::read_into(self, buf, cnt)
+    }
+
+    fn has_input(&self) -> bool {
+        Device::has_input(self)
+    }
+
+    fn flush_input(&mut self) -> MidiRes {
+        Device::flush_input(self)
+    }
+}
+
+/// Make sure no notes are left hanging when a device goes away,
+/// whether by a normal exit or by unwinding from a panic
+impl Drop for Device<'_> {
+    fn drop(&mut self) {
+        if self.output.is_some() {
+            let _ = self.panic();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::devices::mock::MockDevice;
+
+    #[test]
+    fn panic_releases_sustain_and_notes_on_every_channel() {
+        let mut dev = MockDevice::new();
+        dev.panic().unwrap();
+        let sent = dev.sent();
+        assert_eq!(sent.len(), 32);
+        for channel in 0..16u8 {
+            let i = channel as usize * 2;
+            assert_eq!(sent[i], [0xB0 | channel, 64, 0, 0]);
+            assert_eq!(sent[i + 1], [0xB0 | channel, 123, 0, 0]);
+        }
+    }
+
+    #[test]
+    fn flushing_drains_pending_input() {
+        let mut dev = MockDevice::new();
+        for note in 0..10 {
+            dev.inject([0x90, note, 100, 0]);
+        }
+        dev.flush_input().unwrap();
+        assert!(!dev.has_input());
+        assert!(dev.read_n(16).unwrap().is_none());
     }
 
-    pub fn write(&mut self, kind: u8, note: u8, vel: u8, extra: u8) -> bool {
-        self.output.write_message([kind, note, vel, extra]).is_ok()
+    #[test]
+    fn program_changes_are_range_checked() {
+        let mut dev = MockDevice::new();
+        dev.program_change(5, 10).unwrap();
+        dev.program_change(15, 127).unwrap();
+        assert_eq!(dev.sent(), vec![[0xC5, 10, 0, 0], [0xCF, 127, 0, 0]]);
+        assert!(matches!(dev.program_change(16, 0), Err(MidiError::OutOfRange("channel", 16))));
+        assert!(matches!(dev.program_change(0, 128), Err(MidiError::OutOfRange("program", 128))));
+        assert_eq!(dev.sent().len(), 2);
     }
 }
 
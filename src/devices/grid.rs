@@ -0,0 +1,172 @@
+// grid.rs - an abstraction over pad controllers
+
+use crate::devices::launchpad::led_color;
+use crate::types::MidiRes;
+
+/// LEDs in a full frame: 64 pads row by row from the top-left,
+/// then the 8 side column buttons top to bottom, then the 8 top row
+/// buttons left to right
+pub const FRAME_LEN: usize = 80;
+
+/// A button press or release on a pad controller
+/// Pads are addressed by (x, y) from the top-left, the side column
+/// (scene buttons) by row, and the top row by index from the left
+/// Pressure is aftertouch (0-127) from pressure-sensitive pads
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum GridEvent {
+    Pad { x: u8, y: u8, pressed: bool },
+    Side { y: u8, pressed: bool },
+    Top { idx: u8, pressed: bool },
+    Pressure { value: u8 },
+}
+
+/// An 8x8 pad controller with a side column and a top row of buttons
+/// Implement this to run a program on a different controller.
+pub trait Grid {
+    /// Light a pad, or a side column button when x == 8
+    /// A color of 0 turns the LED off
+    fn set_led(&mut self, x: u8, y: u8, color: u8) -> MidiRes;
+
+    /// Light a top row button
+    fn set_top_led(&mut self, idx: u8, color: u8) -> MidiRes;
+
+    /// Turn every LED off
+    fn clear(&mut self) -> MidiRes;
+
+    /// Replace `events` with every button event received since the
+    /// last poll, so one buffer can be reused from poll to poll
+    /// Fails if the device can't be read, e.g. once it's unplugged
+    fn poll(&mut self, events: &mut Vec<GridEvent>) -> MidiRes;
+
+    /// The color value for a red/green mix (each 0-3)
+    fn color(&self, red: u8, green: u8) -> u8;
+
+    /// Push a whole frame of LEDs (see `FRAME_LEN` for the layout)
+    /// By default every LED is set one at a time
+    fn flush_frame(&mut self, frame: &[u8; FRAME_LEN]) -> MidiRes {
+        flush_each(self, frame)
+    }
+
+    /// How many messages `flush_frame()` sends, so callers can tell
+    /// when a whole frame is cheaper than setting LEDs one by one
+    fn frame_messages(&self) -> usize {
+        FRAME_LEN
+    }
+
+    /// Drop any button events that are already waiting
+    fn flush_input(&mut self) -> MidiRes {
+        self.poll(&mut Vec::new())
+    }
+}
+
+/// Push a frame one LED at a time, for grids with no bulk update
+pub fn flush_each<G: Grid + ?Sized>(grid: &mut G, frame: &[u8; FRAME_LEN]) -> MidiRes {
+    for (i, color) in frame.iter().enumerate() {
+        let n = i as u8;
+        match n {
+            0..=63 => grid.set_led(n % 8, n / 8, *color)?,
+            64..=71 => grid.set_led(8, n - 64, *color)?,
+            _ => grid.set_top_led(n - 72, *color)?,
+        }
+    }
+    Ok(())
+}
+
+/// A Grid with no hardware behind it, for tests and dry runs
+/// LED writes land in `leds`/`top`, and events queued with `push()`
+/// are handed out on the next poll
+#[derive(Debug, Clone, Default)]
+pub struct MockGrid {
+    pub leds: [[u8; 9]; 8],
+    pub top: [u8; 8],
+    pub events: Vec<GridEvent>,
+}
+
+impl MockGrid {
+    pub fn new() -> MockGrid {
+        MockGrid::default()
+    }
+
+    /// Queue an event for the next poll
+    pub fn push(&mut self, event: GridEvent) {
+        self.events.push(event);
+    }
+}
+
+impl Grid for MockGrid {
+    fn set_led(&mut self, x: u8, y: u8, color: u8) -> MidiRes {
+        if x < 9 && y < 8 {
+            self.leds[y as usize][x as usize] = color;
+        }
+        Ok(())
+    }
+
+    fn set_top_led(&mut self, idx: u8, color: u8) -> MidiRes {
+        if idx < 8 {
+            self.top[idx as usize] = color;
+        }
+        Ok(())
+    }
+
+    fn clear(&mut self) -> MidiRes {
+        self.leds = [[0; 9]; 8];
+        self.top = [0; 8];
+        Ok(())
+    }
+
+    fn poll(&mut self, events: &mut Vec<GridEvent>) -> MidiRes {
+        events.clear();
+        events.append(&mut self.events);
+        Ok(())
+    }
+
+    fn color(&self, red: u8, green: u8) -> u8 {
+        led_color(red, green)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn mock_grid_hands_out_events_once() {
+        let mut grid = MockGrid::new();
+        grid.push(GridEvent::Pad { x: 1, y: 2, pressed: true });
+        grid.push(GridEvent::Top { idx: 0, pressed: false });
+        let mut events = Vec::new();
+        grid.poll(&mut events).unwrap();
+        assert_eq!(events.len(), 2);
+        grid.poll(&mut events).unwrap();
+        assert!(events.is_empty());
+        grid.push(GridEvent::Side { y: 3, pressed: true });
+        grid.flush_input().unwrap();
+        grid.poll(&mut events).unwrap();
+        assert!(events.is_empty());
+        // out of range LEDs are ignored rather than panicking
+        grid.set_led(9, 0, 1).unwrap();
+        grid.set_top_led(8, 1).unwrap();
+    }
+
+    #[test]
+    fn frames_follow_the_documented_layout() {
+        let mut frame = [0; FRAME_LEN];
+        frame[0] = 1;
+        frame[63] = 2;
+        frame[64] = 3;
+        frame[71] = 4;
+        frame[72] = 5;
+        frame[79] = 6;
+        let mut grid = MockGrid::new();
+        grid.flush_frame(&frame).unwrap();
+        assert_eq!(grid.leds[0][0], 1);
+        assert_eq!(grid.leds[7][7], 2);
+        assert_eq!(grid.leds[0][8], 3);
+        assert_eq!(grid.leds[7][8], 4);
+        assert_eq!(grid.top, [5, 0, 0, 0, 0, 0, 0, 6]);
+        grid.clear().unwrap();
+        assert_eq!(grid.leds, [[0; 9]; 8]);
+    }
+}
+
+// end grid.rs
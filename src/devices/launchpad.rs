@@ -0,0 +1,505 @@
+// launchpad.rs - Novation Launchpad helpers
+
+extern crate portmidi as pm;
+use crate::devices::device::{MidiIo, DEFAULT_BUFFER_SIZE};
+use crate::devices::grid::{flush_each, Grid, GridEvent, FRAME_LEN};
+use crate::types::{MidiError, MidiMessage, MidiParser, MidiRes};
+
+/// Which generation of Launchpad is attached
+/// The mk1 has two-color (red/green) LEDs driven by velocity bits,
+/// while the mk2 and mk3 are RGB and read velocity as a palette index
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum LaunchpadModel {
+    Mk1,
+    Mk2,
+    Mk3,
+}
+
+/// Closest mk2/mk3 palette entries for each mk1 (red, green) mix,
+/// indexed as [red][green]
+const PALETTE: [[u8; 4]; 4] = [
+    [0, 23, 22, 21],
+    [7, 19, 18, 17],
+    [6, 11, 14, 13],
+    [5, 9, 61, 13],
+];
+
+/// Messages that push a whole frame to a mk1 using its rapid LED
+/// update mode: two LEDs per note-on on channel 3, in the same order
+/// as the frame. The mapping-mode message up front resets the
+/// device's update cursor to the first pad.
+pub fn rapid_update(frame: &[u8; FRAME_LEN]) -> Vec<[u8; 4]> {
+    let mut msgs = vec![[0xB0, 0x00, 0x01, 0]];
+    for pair in frame.chunks(2) {
+        msgs.push([0x92, pair[0], pair[1], 0]);
+    }
+    msgs
+}
+
+/// Calculate the LED color on the Launchpad mk1
+/// Launchpad only has two color options for LEDs, Red and Green,
+/// each with 3 levels of brightness
+pub fn led_color(red: u8, green: u8) -> u8 {
+    match (red, green) {
+        (0..=3, 0..=3) => 12 | red | (16 * green),
+        _ => 127,
+    }
+}
+
+impl LaunchpadModel {
+    /// Parse a model from a short name like "mk1", "mk2" or "mk3"
+    pub fn from_name(name: &str) -> Option<LaunchpadModel> {
+        match name.to_lowercase().as_str() {
+            "mk1" => Some(LaunchpadModel::Mk1),
+            "mk2" => Some(LaunchpadModel::Mk2),
+            "mk3" => Some(LaunchpadModel::Mk3),
+            _ => None,
+        }
+    }
+
+    /// The velocity byte that lights an LED with a red/green mix
+    /// (each 0-3) on this model
+    pub fn color(&self, red: u8, green: u8) -> u8 {
+        match self {
+            LaunchpadModel::Mk1 => led_color(red, green),
+            _ => PALETTE[red.min(3) as usize][green.min(3) as usize],
+        }
+    }
+
+    /// A sysex message setting one LED to an exact RGB color
+    /// Channels are 0-127 and scaled down to 0-63 on the mk2.
+    /// The mk1 has no RGB LEDs, so there is no message for it.
+    pub fn rgb_sysex(&self, led: u8, r: u8, g: u8, b: u8) -> Option<Vec<u8>> {
+        let (r, g, b) = (r.min(127), g.min(127), b.min(127));
+        match self {
+            LaunchpadModel::Mk1 => None,
+            LaunchpadModel::Mk2 => Some(vec![
+                0xF0, 0x00, 0x20, 0x29, 0x02, 0x18, 0x0B, led, r / 2, g / 2, b / 2, 0xF7,
+            ]),
+            LaunchpadModel::Mk3 => Some(vec![
+                0xF0, 0x00, 0x20, 0x29, 0x02, 0x0D, 0x03, 0x03, led, r, g, b, 0xF7,
+            ]),
+        }
+    }
+
+    /// The sysex that switches programmer mode on or off, where pads
+    /// are numbered 11-88 from the bottom-left and scene buttons end
+    /// each row on 19-89. The mk1 has no such mode, and the mk2 has
+    /// nothing to switch back to, so those get no message.
+    pub fn programmer_sysex(&self, enter: bool) -> Option<Vec<u8>> {
+        match (self, enter) {
+            (LaunchpadModel::Mk1, _) => None,
+            // the session layout numbers its pads programmer-style
+            (LaunchpadModel::Mk2, true) => Some(vec![0xF0, 0x00, 0x20, 0x29, 0x02, 0x18, 0x22, 0x00, 0xF7]),
+            (LaunchpadModel::Mk2, _) => None,
+            (LaunchpadModel::Mk3, _) => Some(vec![0xF0, 0x00, 0x20, 0x29, 0x02, 0x0D, 0x0E, enter as u8, 0xF7]),
+        }
+    }
+
+    /// The note (or on the mk3, CC for scene buttons) addressing a
+    /// grid position, the side column being x == 8
+    /// The mk1 counts `y*16 + x` from the top-left, the others count
+    /// in programmer mode, rows of ten from the bottom-left
+    pub fn note_from_xy(&self, x: u8, y: u8) -> u8 {
+        match self {
+            LaunchpadModel::Mk1 => (y * 16) + x,
+            _ => ((8 - y.min(7)) * 10) + x + 1,
+        }
+    }
+
+    /// The grid position (x == 8 for the side column) of a note,
+    /// the inverse of `note_from_xy()`
+    pub fn xy_from_note(&self, note: u8) -> Option<(u8, u8)> {
+        let (x, y) = match self {
+            LaunchpadModel::Mk1 => (note % 16, note / 16),
+            _ => ((note % 10).wrapping_sub(1), 8u8.wrapping_sub(note / 10)),
+        };
+        match x <= 8 && y < 8 {
+            true => Some((x, y)),
+            _ => None,
+        }
+    }
+
+    /// The CC of a top row button
+    pub fn top_cc(&self, idx: u8) -> u8 {
+        match self {
+            LaunchpadModel::Mk3 => 91 + idx,
+            _ => 104 + idx,
+        }
+    }
+
+    /// A sysex message that scrolls text across the grid once
+    /// Speed runs from 1 (slowest) to 7 (fastest), and only printable
+    /// ASCII is kept since the devices have no glyphs for anything else.
+    /// The mk1 here means the S/mini generation, the original mk1
+    /// firmware has no text scrolling and ignores the message.
+    pub fn scroll_sysex(&self, text: &str, color: u8, speed: u8) -> Vec<u8> {
+        let speed = speed.clamp(1, 7);
+        let text = text.bytes().filter(|b| (0x20..0x7F).contains(b));
+        let mut msg: Vec<u8> = match self {
+            LaunchpadModel::Mk1 => vec![0xF0, 0x00, 0x20, 0x29, 0x09, color & 0x7F, speed],
+            LaunchpadModel::Mk2 => vec![0xF0, 0x00, 0x20, 0x29, 0x02, 0x18, 0x14, color & 0x7F, 0x00, speed],
+            LaunchpadModel::Mk3 => vec![0xF0, 0x00, 0x20, 0x29, 0x02, 0x0D, 0x07, 0x00, speed, 0x00, color & 0x7F],
+        };
+        msg.extend(text);
+        msg.push(0xF7);
+        msg
+    }
+}
+
+/// A Launchpad grid: 8x8 pads plus a side column of scene buttons
+/// (x == 8) on notes `y*16 + x`, and a top row of buttons on CC 104-111.
+/// Wraps the underlying device and knows how to address and color LEDs.
+pub struct Launchpad<'a> {
+    pub device: Box<dyn MidiIo + 'a>,
+    pub model: LaunchpadModel,
+    parser: MidiParser,
+    input_buf: Vec<pm::MidiEvent>,
+}
+
+impl<'a> Launchpad<'a> {
+    pub fn new<D: MidiIo + 'a>(device: D, model: LaunchpadModel) -> Launchpad<'a> {
+        Launchpad {
+            device: Box::new(device),
+            model,
+            parser: MidiParser::new(),
+            input_buf: Vec::with_capacity(DEFAULT_BUFFER_SIZE),
+        }
+    }
+
+    /// Converts a MIDI note to the (x, y) of an 8x8 pad on the mk1
+    /// Returns None for the side column, the unused notes 9-15 of
+    /// each row of 16, and anything past the last row
+    ///
+    /// xy_from_note(50) -> Some((2, 3))
+    /// xy_from_note(8) -> None
+    /// xy_from_note(200) -> None
+    pub fn xy_from_note(note: u8) -> Option<(u8, u8)> {
+        let (x, y) = (note % 16, note / 16);
+        match x < 8 && y < 8 {
+            true => Some((x, y)),
+            _ => None,
+        }
+    }
+
+    /// Converts a MIDI note to the row of an mk1 side column button
+    ///
+    /// side_from_note(24) -> Some(1)
+    /// side_from_note(23) -> None
+    pub fn side_from_note(note: u8) -> Option<u8> {
+        let (x, y) = (note % 16, note / 16);
+        match x == 8 && y < 8 {
+            true => Some(y),
+            _ => None,
+        }
+    }
+
+    /// A pad or scene button event from a note
+    fn grid_event(model: LaunchpadModel, note: u8, pressed: bool) -> Option<GridEvent> {
+        match model.xy_from_note(note)? {
+            (8, y) => Some(GridEvent::Side { y, pressed }),
+            (x, y) => Some(GridEvent::Pad { x, y, pressed }),
+        }
+    }
+
+    /// The MIDI note for a grid position on the mk1
+    /// See `LaunchpadModel::note_from_xy()` for the other models
+    pub fn note_from_xy(x: u8, y: u8) -> u8 {
+        (y * 16) + x
+    }
+
+    /// The velocity byte for a red/green mix on this model
+    pub fn color(&self, red: u8, green: u8) -> u8 {
+        self.model.color(red, green)
+    }
+
+    /// Light the LED at a grid position, 0 turns it off
+    /// Scene buttons on the mk3 are lit by CC rather than note
+    pub fn set_led(&mut self, x: u8, y: u8, color: u8) -> MidiRes {
+        let status = match (self.model, x) {
+            (LaunchpadModel::Mk3, 8) => 0xB0,
+            _ => 0x90,
+        };
+        self.device.write_message([status, self.model.note_from_xy(x, y), color, 0])
+    }
+
+    /// Put an mk2/mk3 into programmer mode so its buttons line up
+    /// with `note_from_xy()`. Does nothing on the mk1.
+    pub fn enter_programmer_mode(&mut self) -> MidiRes {
+        match self.model.programmer_sysex(true) {
+            Some(msg) => self.device.write_sysex(&msg),
+            None => Ok(()),
+        }
+    }
+
+    /// Hand the device back to its own mode, done on drop as well
+    pub fn exit_programmer_mode(&mut self) -> MidiRes {
+        match self.model.programmer_sysex(false) {
+            Some(msg) => self.device.write_sysex(&msg),
+            None => Ok(()),
+        }
+    }
+
+    /// Scroll a short message across the grid, e.g. the key or tempo
+    pub fn scroll_text(&mut self, text: &str, color: u8, speed: u8) -> MidiRes {
+        let msg = self.model.scroll_sysex(text, color, speed);
+        self.device.write_sysex(&msg)
+    }
+
+    /// Reset the device, turning every LED off
+    /// Only the mk1 has a reset message, the others get a blank frame
+    pub fn clear(&mut self) -> MidiRes {
+        match self.model {
+            LaunchpadModel::Mk1 => self.device.write_message([0xB0, 0, 0, 0]),
+            _ => flush_each(self, &[0; FRAME_LEN]),
+        }
+    }
+
+    /// Write a raw message to the device
+    pub fn write_message(&mut self, msg: [u8; 4]) -> MidiRes {
+        self.device.write_message(msg)
+    }
+
+    /// Write a message from its individual bytes
+    pub fn write(&mut self, kind: u8, note: u8, vel: u8, extra: u8) -> MidiRes {
+        self.device.write_message([kind, note, vel, extra])
+    }
+
+    /// Read up to `cnt` button events
+    pub fn read_n(&self, cnt: usize) -> Result<Option<Vec<pm::MidiEvent>>, MidiError> {
+        self.device.read_n(cnt)
+    }
+
+    /// Whether any button events are waiting
+    pub fn has_input(&self) -> bool {
+        self.device.has_input()
+    }
+
+    /// Drop any button presses that are already waiting
+    pub fn flush_input(&mut self) -> MidiRes {
+        self.device.flush_input()
+    }
+}
+
+impl Grid for Launchpad<'_> {
+    fn set_led(&mut self, x: u8, y: u8, color: u8) -> MidiRes {
+        Launchpad::set_led(self, x, y, color)
+    }
+
+    fn set_top_led(&mut self, idx: u8, color: u8) -> MidiRes {
+        self.device.write_message([0xB0, self.model.top_cc(idx), color, 0])
+    }
+
+    fn clear(&mut self) -> MidiRes {
+        Launchpad::clear(self)
+    }
+
+    /// Top row buttons arrive as CCs (value 0 on release), pads and
+    /// scene buttons as notes (velocity 0 on release), except for the
+    /// mk3's scene buttons which are CCs as well
+    fn poll(&mut self, events: &mut Vec<GridEvent>) -> MidiRes {
+        events.clear();
+        if !self.has_input() {
+            return Ok(());
+        }
+        self.device.read_into(&mut self.input_buf, DEFAULT_BUFFER_SIZE)?;
+        let (parser, model) = (&mut self.parser, self.model);
+        let top = model.top_cc(0);
+        events.extend(self.input_buf
+            .drain(..)
+            .filter_map(|e| match parser.parse(e)? {
+                MidiMessage::ControlChange { controller, value, .. } if (top..top + 8).contains(&controller) => {
+                    Some(GridEvent::Top { idx: controller - top, pressed: value > 0 })
+                }
+                MidiMessage::ControlChange { controller, value, .. } if model == LaunchpadModel::Mk3 => {
+                    Self::grid_event(model, controller, value > 0)
+                }
+                MidiMessage::NoteOn { note, .. } => Self::grid_event(model, note, true),
+                MidiMessage::NoteOff { note, .. } => Self::grid_event(model, note, false),
+                MidiMessage::ChannelPressure { value, .. } | MidiMessage::PolyAftertouch { value, .. } => {
+                    Some(GridEvent::Pressure { value })
+                }
+                _ => None,
+            }));
+        Ok(())
+    }
+
+    fn color(&self, red: u8, green: u8) -> u8 {
+        Launchpad::color(self, red, green)
+    }
+
+    /// The mk1 takes the whole frame in 41 messages instead of 80
+    fn flush_frame(&mut self, frame: &[u8; FRAME_LEN]) -> MidiRes {
+        match self.model {
+            LaunchpadModel::Mk1 => {
+                for msg in rapid_update(frame) {
+                    self.device.write_message(msg)?;
+                }
+                Ok(())
+            }
+            _ => flush_each(self, frame),
+        }
+    }
+
+    fn frame_messages(&self) -> usize {
+        match self.model {
+            LaunchpadModel::Mk1 => FRAME_LEN / 2 + 1,
+            _ => FRAME_LEN,
+        }
+    }
+
+    fn flush_input(&mut self) -> MidiRes {
+        Launchpad::flush_input(self)
+    }
+}
+
+/// Leave an mk3 the way it was found
+impl Drop for Launchpad<'_> {
+    fn drop(&mut self) {
+        let _ = self.exit_programmer_mode();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::devices::mock::MockDevice;
+
+    const MODELS: [LaunchpadModel; 3] = [LaunchpadModel::Mk1, LaunchpadModel::Mk2, LaunchpadModel::Mk3];
+
+    #[test]
+    fn mk1_colors() {
+        assert_eq!(led_color(0, 0), 12);
+        assert_eq!(led_color(3, 0), 15);
+        assert_eq!(led_color(0, 3), 60);
+        assert_eq!(led_color(3, 3), 63);
+        assert_eq!(led_color(4, 0), 127);
+        assert_eq!(LaunchpadModel::Mk2.color(3, 0), 5);
+        assert_eq!(LaunchpadModel::Mk3.color(9, 9), 13);
+    }
+
+    #[test]
+    fn model_names() {
+        assert_eq!(LaunchpadModel::from_name("MK2"), Some(LaunchpadModel::Mk2));
+        assert_eq!(LaunchpadModel::from_name("mk3"), Some(LaunchpadModel::Mk3));
+        assert_eq!(LaunchpadModel::from_name("mk4"), None);
+    }
+
+    #[test]
+    fn rapid_update_packs_two_leds_a_message() {
+        let mut frame = [0; FRAME_LEN];
+        for (i, led) in frame.iter_mut().enumerate() {
+            *led = i as u8;
+        }
+        let msgs = rapid_update(&frame);
+        assert_eq!(msgs.len(), FRAME_LEN / 2 + 1);
+        assert_eq!(msgs[0], [0xB0, 0x00, 0x01, 0]);
+        assert_eq!(msgs[1], [0x92, 0, 1, 0]);
+        assert_eq!(msgs[40], [0x92, 78, 79, 0]);
+    }
+
+    #[test]
+    fn clearing_and_whole_frames() {
+        let dev = MockDevice::new();
+        let mut lp = Launchpad::new(dev.clone(), LaunchpadModel::Mk1);
+        Grid::clear(&mut lp).unwrap();
+        assert_eq!(dev.sent(), vec![[0xB0, 0, 0, 0]]);
+        dev.clear_sent();
+        lp.flush_frame(&[0; FRAME_LEN]).unwrap();
+        assert_eq!(dev.sent().len(), lp.frame_messages());
+
+        let dev = MockDevice::new();
+        let mut lp = Launchpad::new(dev.clone(), LaunchpadModel::Mk2);
+        Grid::clear(&mut lp).unwrap();
+        assert_eq!(dev.sent().len(), FRAME_LEN);
+        assert_eq!(lp.frame_messages(), FRAME_LEN);
+    }
+
+    #[test]
+    fn scroll_sysex_keeps_printable_text() {
+        let msg = LaunchpadModel::Mk1.scroll_sysex("a\u{e9}b\n", 0xFF, 9);
+        assert_eq!(msg, vec![0xF0, 0x00, 0x20, 0x29, 0x09, 0x7F, 7, b'a', b'b', 0xF7]);
+        for model in MODELS {
+            let msg = model.scroll_sysex("hi", 5, 0);
+            assert_eq!(msg[0], 0xF0);
+            assert_eq!(msg.last(), Some(&0xF7));
+            assert!(msg.ends_with(b"hi\xF7"));
+        }
+    }
+
+    #[test]
+    fn grid_positions_round_trip_through_notes() {
+        for model in MODELS {
+            for y in 0..8 {
+                for x in 0..=8 {
+                    assert_eq!(model.xy_from_note(model.note_from_xy(x, y)), Some((x, y)));
+                }
+            }
+        }
+        assert_eq!(LaunchpadModel::Mk1.note_from_xy(2, 3), 50);
+        assert_eq!(LaunchpadModel::Mk3.note_from_xy(0, 0), 81);
+        assert_eq!(LaunchpadModel::Mk3.note_from_xy(8, 7), 19);
+        assert_eq!(LaunchpadModel::Mk1.xy_from_note(9), None);
+        assert_eq!(LaunchpadModel::Mk2.xy_from_note(10), None);
+        assert_eq!(Launchpad::xy_from_note(50), Some((2, 3)));
+        assert_eq!(Launchpad::xy_from_note(8), None);
+        assert_eq!(Launchpad::side_from_note(24), Some(1));
+        assert_eq!(Launchpad::side_from_note(23), None);
+    }
+
+    #[test]
+    fn programmer_mode_sysex() {
+        assert_eq!(LaunchpadModel::Mk1.programmer_sysex(true), None);
+        assert_eq!(LaunchpadModel::Mk2.programmer_sysex(false), None);
+        let enter = LaunchpadModel::Mk3.programmer_sysex(true).unwrap();
+        let exit = LaunchpadModel::Mk3.programmer_sysex(false).unwrap();
+        assert_eq!(enter, vec![0xF0, 0x00, 0x20, 0x29, 0x02, 0x0D, 0x0E, 1, 0xF7]);
+        assert_eq!(exit[7], 0);
+    }
+
+    #[test]
+    fn mk3_scene_buttons_are_ccs() {
+        let dev = MockDevice::new();
+        let mut lp = Launchpad::new(dev.clone(), LaunchpadModel::Mk3);
+        lp.set_led(8, 0, 5).unwrap();
+        lp.set_led(0, 0, 5).unwrap();
+        assert_eq!(dev.sent(), vec![[0xB0, 89, 5, 0], [0x90, 81, 5, 0]]);
+        dev.inject([0xB0, 89, 127, 0]);
+        dev.inject([0xB0, 91, 0, 0]);
+        let mut events = Vec::new();
+        lp.poll(&mut events).unwrap();
+        assert_eq!(
+            events,
+            vec![GridEvent::Side { y: 0, pressed: true }, GridEvent::Top { idx: 0, pressed: false }]
+        );
+    }
+
+    #[test]
+    fn poll_turns_messages_into_grid_events() {
+        let dev = MockDevice::new();
+        let mut lp = Launchpad::new(dev.clone(), LaunchpadModel::Mk1);
+        dev.inject([0x90, 50, 127, 0]);
+        // running status release of the same pad
+        dev.inject([50, 0, 0, 0]);
+        dev.inject([0x90, 24, 127, 0]);
+        dev.inject([0xB0, 105, 127, 0]);
+        dev.inject([0xD0, 80, 0, 0]);
+        dev.inject([0x90, 9, 127, 0]);
+        let mut events = Vec::new();
+        lp.poll(&mut events).unwrap();
+        assert_eq!(
+            events,
+            vec![
+                GridEvent::Pad { x: 2, y: 3, pressed: true },
+                GridEvent::Pad { x: 2, y: 3, pressed: false },
+                GridEvent::Side { y: 1, pressed: true },
+                GridEvent::Top { idx: 1, pressed: true },
+                GridEvent::Pressure { value: 80 },
+            ]
+        );
+        lp.poll(&mut events).unwrap();
+        assert!(events.is_empty());
+    }
+}
+
+// end launchpad.rs
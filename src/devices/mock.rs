@@ -0,0 +1,148 @@
+// mock.rs - a hardware-free MIDI device for tests
+
+extern crate portmidi as pm;
+use std::cell::{Cell, RefCell};
+use std::collections::VecDeque;
+use std::rc::Rc;
+
+use crate::devices::device::MidiIo;
+use crate::types::{MidiError, MidiRes};
+
+/// A MidiIo that records everything written to it and plays back
+/// injected input events. Clones share the same buffers, so keep a
+/// clone around to inspect a mock after handing it to something else.
+/// While failing, every read and write errors as if it were unplugged.
+#[derive(Debug, Clone, Default)]
+pub struct MockDevice {
+    sent: Rc<RefCell<Vec<[u8; 4]>>>,
+    sysex: Rc<RefCell<Vec<Vec<u8>>>>,
+    input: Rc<RefCell<VecDeque<pm::MidiEvent>>>,
+    failing: Rc<Cell<bool>>,
+    reads: Rc<Cell<usize>>,
+}
+
+impl MockDevice {
+    pub fn new() -> MockDevice {
+        MockDevice::default()
+    }
+
+    /// Every message written so far, oldest first
+    pub fn sent(&self) -> Vec<[u8; 4]> {
+        self.sent.borrow().clone()
+    }
+
+    /// Every sysex message written so far, oldest first
+    pub fn sent_sysex(&self) -> Vec<Vec<u8>> {
+        self.sysex.borrow().clone()
+    }
+
+    /// Forget everything written so far
+    pub fn clear_sent(&self) {
+        self.sent.borrow_mut().clear();
+        self.sysex.borrow_mut().clear();
+    }
+
+    /// Queue a raw message to be read back as input
+    pub fn inject(&self, msg: [u8; 4]) {
+        self.input.borrow_mut().push_back(pm::MidiEvent {
+            message: pm::MidiMessage::from(msg),
+            timestamp: 0,
+        });
+    }
+
+    /// How many times input has been read, empty or not
+    pub fn reads(&self) -> usize {
+        self.reads.get()
+    }
+
+    /// Make every read and write fail until set back to false
+    pub fn set_failing(&self, failing: bool) {
+        self.failing.set(failing);
+    }
+
+    fn check(&self) -> MidiRes {
+        match self.failing.get() {
+            true => Err(MidiError::PortMidi(pm::Error::Unknown)),
+            _ => Ok(()),
+        }
+    }
+}
+
+impl MidiIo for MockDevice {
+    fn write_message(&mut self, msg: [u8; 4]) -> MidiRes {
+        self.check()?;
+        self.sent.borrow_mut().push(msg);
+        Ok(())
+    }
+
+    fn write_sysex(&mut self, msg: &[u8]) -> MidiRes {
+        self.check()?;
+        self.sysex.borrow_mut().push(msg.to_vec());
+        Ok(())
+    }
+
+    fn read_n(&self, cnt: usize) -> Result<Option<Vec<pm::MidiEvent>>, MidiError> {
+        self.reads.set(self.reads.get() + 1);
+        self.check()?;
+        let mut input = self.input.borrow_mut();
+        let n = cnt.min(input.len());
+        match n {
+            0 => Ok(None),
+            _ => Ok(Some(input.drain(..n).collect())),
+        }
+    }
+
+    fn read_into(&mut self, buf: &mut Vec<pm::MidiEvent>, cnt: usize) -> Result<usize, MidiError> {
+        buf.clear();
+        self.reads.set(self.reads.get() + 1);
+        self.check()?;
+        let mut input = self.input.borrow_mut();
+        let n = cnt.min(input.len());
+        buf.extend(input.drain(..n));
+        Ok(n)
+    }
+
+    fn has_input(&self) -> bool {
+        self.failing.get() || !self.input.borrow().is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn records_writes_and_replays_input() {
+        let mut dev = MockDevice::new();
+        let watch = dev.clone();
+        dev.write_message([0x90, 60, 100, 0]).unwrap();
+        dev.write_sysex(&[0xF0, 0x7E, 0xF7]).unwrap();
+        assert_eq!(watch.sent(), vec![[0x90, 60, 100, 0]]);
+        assert_eq!(watch.sent_sysex(), vec![vec![0xF0, 0x7E, 0xF7]]);
+
+        assert!(!dev.has_input());
+        watch.inject([0x80, 60, 0, 0]);
+        assert!(dev.has_input());
+        let mut buf = Vec::new();
+        assert_eq!(dev.read_into(&mut buf, 16).unwrap(), 1);
+        assert_eq!(buf[0].message.status, 0x80);
+        assert!(!dev.has_input());
+    }
+
+    #[test]
+    fn fails_then_recovers() {
+        let mut dev = MockDevice::new();
+        dev.set_failing(true);
+        assert!(dev.has_input());
+        assert!(dev.write_message([0x90, 60, 100, 0]).is_err());
+        assert!(dev.read_into(&mut Vec::new(), 16).is_err());
+        assert!(dev.sent().is_empty());
+
+        dev.set_failing(false);
+        assert!(!dev.has_input());
+        dev.write_message([0x90, 60, 100, 0]).unwrap();
+        assert_eq!(dev.sent().len(), 1);
+    }
+}
+
+// end mock.rs
@@ -1,2 +1,4 @@
 pub mod device;
-//pub mod launchpad;
+pub mod grid;
+pub mod launchpad;
+pub mod mock;
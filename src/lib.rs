@@ -1,2 +1,9 @@
 pub mod devices;
+#[cfg(feature = "osc")]
+pub mod osc;
+pub mod patterns;
+pub mod rng;
+pub mod scheduler;
+pub mod sequencer;
+pub mod smf;
 pub mod types;
@@ -0,0 +1,232 @@
+// osc.rs - a minimal Open Sound Control sender and listener over UDP
+
+use std::io;
+use std::net::{SocketAddr, ToSocketAddrs, UdpSocket};
+use std::sync::mpsc::{self, Receiver};
+use std::thread;
+
+/// An OSC message argument
+#[derive(Debug, Clone, PartialEq)]
+pub enum OscArg {
+    Int(i32),
+    Float(f32),
+    Str(String),
+}
+
+/// Append an OSC string: the bytes, a terminating null, then
+/// null padding out to a multiple of four bytes
+fn write_str(out: &mut Vec<u8>, s: &str) {
+    out.extend(s.as_bytes());
+    out.push(0);
+    while !out.len().is_multiple_of(4) {
+        out.push(0);
+    }
+}
+
+/// Encode a single OSC message, e.g. `/note ,ii 60 100`
+pub fn message_bytes(addr: &str, args: &[OscArg]) -> Vec<u8> {
+    let mut out = Vec::new();
+    write_str(&mut out, addr);
+    let tags: String = args
+        .iter()
+        .map(|a| match a {
+            OscArg::Int(_) => 'i',
+            OscArg::Float(_) => 'f',
+            OscArg::Str(_) => 's',
+        })
+        .collect();
+    write_str(&mut out, &format!(",{}", tags));
+    for arg in args {
+        match arg {
+            OscArg::Int(i) => out.extend(i.to_be_bytes()),
+            OscArg::Float(f) => out.extend(f.to_be_bytes()),
+            OscArg::Str(s) => write_str(&mut out, s),
+        }
+    }
+    out
+}
+
+/// Wrap encoded messages in a bundle timed "immediately"
+pub fn bundle_bytes(messages: &[Vec<u8>]) -> Vec<u8> {
+    let mut out = Vec::new();
+    write_str(&mut out, "#bundle");
+    out.extend(1u64.to_be_bytes());
+    for msg in messages {
+        out.extend((msg.len() as i32).to_be_bytes());
+        out.extend(msg);
+    }
+    out
+}
+
+/// Read an OSC string starting at `pos`, returning it along with
+/// the position just past its padding
+fn read_str(buf: &[u8], pos: usize) -> Option<(String, usize)> {
+    let len = buf.get(pos..)?.iter().position(|b| *b == 0)?;
+    let s = String::from_utf8(buf[pos..pos + len].to_vec()).ok()?;
+    let next = (pos + len + 4) & !3;
+    Some((s, next))
+}
+
+fn read_be4(buf: &[u8], pos: usize) -> Option<[u8; 4]> {
+    buf.get(pos..pos + 4)?.try_into().ok()
+}
+
+/// Decode a packet into its messages, unpacking bundles (nested ones
+/// too). Arguments of types other than int, float and string stop
+/// the message from decoding. Returns None on a malformed packet.
+pub fn parse_packet(buf: &[u8]) -> Option<Vec<(String, Vec<OscArg>)>> {
+    if buf.starts_with(b"#bundle\0") {
+        let mut out = Vec::new();
+        let mut pos = 16;
+        while pos < buf.len() {
+            // a negative or oversized element size is malformed
+            let size = usize::try_from(i32::from_be_bytes(read_be4(buf, pos)?)).ok()?;
+            let end = pos.checked_add(4)?.checked_add(size)?;
+            out.extend(parse_packet(buf.get(pos + 4..end)?)?);
+            pos = end;
+        }
+        return Some(out);
+    }
+    let (addr, pos) = read_str(buf, 0)?;
+    let (tags, mut pos) = read_str(buf, pos)?;
+    let mut args = Vec::new();
+    for tag in tags.strip_prefix(',')?.chars() {
+        let arg = match tag {
+            'i' => OscArg::Int(i32::from_be_bytes(read_be4(buf, pos)?)),
+            'f' => OscArg::Float(f32::from_be_bytes(read_be4(buf, pos)?)),
+            's' => {
+                let (s, next) = read_str(buf, pos)?;
+                pos = next;
+                args.push(OscArg::Str(s));
+                continue;
+            }
+            _ => return None,
+        };
+        pos += 4;
+        args.push(arg);
+    }
+    Some(vec![(addr, args)])
+}
+
+/// Sends OSC packets to a single host:port
+pub struct OscSender {
+    socket: UdpSocket,
+}
+
+impl OscSender {
+    /// Bind a local socket and point it at `target`, e.g. "127.0.0.1:9000"
+    pub fn new<A: ToSocketAddrs>(target: A) -> io::Result<OscSender> {
+        let socket = UdpSocket::bind("0.0.0.0:0")?;
+        socket.connect(target)?;
+        Ok(OscSender { socket })
+    }
+
+    /// Send a single message
+    pub fn send(&self, addr: &str, args: &[OscArg]) -> io::Result<()> {
+        self.socket.send(&message_bytes(addr, args))?;
+        Ok(())
+    }
+
+    /// Send a `/note <pitch> <velocity>` message in a bundle
+    pub fn send_note(&self, pitch: u8, vel: u8) -> io::Result<()> {
+        let note = message_bytes("/note", &[OscArg::Int(pitch as i32), OscArg::Int(vel as i32)]);
+        self.socket.send(&bundle_bytes(&[note]))?;
+        Ok(())
+    }
+}
+
+/// Listens for OSC packets on a background thread. Decoded messages
+/// are queued and handed out by `poll()`, so they can be applied from
+/// the owner's own loop rather than the listener thread.
+pub struct OscListener {
+    rx: Receiver<(String, Vec<OscArg>)>,
+    addr: SocketAddr,
+}
+
+impl OscListener {
+    /// Bind to `addr`, e.g. "0.0.0.0:9001", and start listening
+    pub fn bind<A: ToSocketAddrs>(addr: A) -> io::Result<OscListener> {
+        let socket = UdpSocket::bind(addr)?;
+        let addr = socket.local_addr()?;
+        let (tx, rx) = mpsc::channel();
+        thread::spawn(move || {
+            let mut buf = [0u8; 1536];
+            while let Ok(len) = socket.recv(&mut buf) {
+                for msg in parse_packet(&buf[..len]).unwrap_or_default() {
+                    if tx.send(msg).is_err() {
+                        return;
+                    }
+                }
+            }
+        });
+        Ok(OscListener { rx, addr })
+    }
+
+    /// The address actually bound, e.g. to find the port picked
+    /// when binding to port 0
+    pub fn local_addr(&self) -> SocketAddr {
+        self.addr
+    }
+
+    /// Every message received since the last poll
+    pub fn poll(&self) -> Vec<(String, Vec<OscArg>)> {
+        self.rx.try_iter().collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::{Duration, Instant};
+
+    #[test]
+    fn messages_round_trip() {
+        let args = vec![OscArg::Int(-3), OscArg::Float(0.5), OscArg::Str("abc".into())];
+        let bytes = message_bytes("/x", &args);
+        assert_eq!(bytes.len() % 4, 0);
+        assert_eq!(parse_packet(&bytes), Some(vec![("/x".to_string(), args)]));
+    }
+
+    #[test]
+    fn bundles_unpack_in_order() {
+        let a = message_bytes("/play", &[]);
+        let b = message_bytes("/bpm", &[OscArg::Int(128)]);
+        let msgs = parse_packet(&bundle_bytes(&[a, b])).unwrap();
+        assert_eq!(msgs[0], ("/play".to_string(), vec![]));
+        assert_eq!(msgs[1], ("/bpm".to_string(), vec![OscArg::Int(128)]));
+    }
+
+    #[test]
+    fn malformed_packets_are_rejected() {
+        let msg = message_bytes("/bpm", &[OscArg::Int(128)]);
+        assert_eq!(parse_packet(&msg[..msg.len() - 2]), None);
+        assert_eq!(parse_packet(b"/x\0\0,b\0\0"), None);
+
+        // negative and huge element sizes must not overflow
+        for size in [-1i32, -4, i32::MAX] {
+            let mut bundle = bundle_bytes(&[]);
+            bundle.extend(size.to_be_bytes());
+            bundle.extend(&msg);
+            assert_eq!(parse_packet(&bundle), None);
+        }
+    }
+
+    #[test]
+    fn loopback_note_reaches_the_listener() {
+        let listener = OscListener::bind("127.0.0.1:0").unwrap();
+        let sender = OscSender::new(listener.local_addr()).unwrap();
+        sender.send_note(60, 100).unwrap();
+        sender.send("/pause", &[]).unwrap();
+
+        let mut got = Vec::new();
+        let started = Instant::now();
+        while got.len() < 2 && started.elapsed() < Duration::from_secs(2) {
+            got.extend(listener.poll());
+            thread::sleep(Duration::from_millis(5));
+        }
+        assert_eq!(got[0], ("/note".to_string(), vec![OscArg::Int(60), OscArg::Int(100)]));
+        assert_eq!(got[1], ("/pause".to_string(), vec![]));
+    }
+}
+
+// end osc.rs
@@ -0,0 +1,60 @@
+// patterns.rs - rhythm and pattern generators
+
+/// Spread `hits` onsets as evenly as possible across `steps` using
+/// Bjorklund's algorithm, e.g. E(3, 8) = x..x..x.
+/// Hits past the number of steps are clamped so every step is a hit.
+pub fn euclidean(hits: usize, steps: usize) -> Vec<bool> {
+    let hits = hits.min(steps);
+    let mut a: Vec<Vec<bool>> = vec![vec![true]; hits];
+    let mut b: Vec<Vec<bool>> = vec![vec![false]; steps - hits];
+
+    // keep pairing the remainders onto the front groups
+    // until at most one remainder group is left over
+    while b.len() > 1 && !a.is_empty() {
+        let n = a.len().min(b.len());
+        let rest = match a.len() > n {
+            true => a[n..].to_vec(),
+            _ => b[n..].to_vec(),
+        };
+        a = a.iter()
+            .zip(b.iter())
+            .map(|(x, y)| x.iter().chain(y.iter()).copied().collect())
+            .collect();
+        b = rest;
+    }
+    a.into_iter().chain(b).flatten().collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A pattern as a string, x for a hit and . for a rest
+    fn show(pattern: &[bool]) -> String {
+        pattern.iter().map(|hit| if *hit { 'x' } else { '.' }).collect()
+    }
+
+    #[test]
+    fn known_euclidean_rhythms() {
+        assert_eq!(show(&euclidean(3, 8)), "x..x..x.");
+        assert_eq!(show(&euclidean(5, 8)), "x.xx.xx.");
+        assert_eq!(show(&euclidean(2, 5)), "x.x..");
+        assert_eq!(show(&euclidean(4, 16)), "x...x...x...x...");
+    }
+
+    #[test]
+    fn every_hit_lands_on_its_own_step() {
+        for steps in 0..=32 {
+            for hits in 0..=steps + 2 {
+                let pattern = euclidean(hits, steps);
+                assert_eq!(pattern.len(), steps);
+                assert_eq!(pattern.iter().filter(|hit| **hit).count(), hits.min(steps));
+                if hits > 0 && steps > 0 {
+                    assert!(pattern[0]);
+                }
+            }
+        }
+    }
+}
+
+// end patterns.rs
@@ -0,0 +1,717 @@
+// scheduler.rs - a tick-based job scheduler
+
+use std::cell::Cell;
+use std::cmp::Reverse;
+use std::collections::BinaryHeap;
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// A source of time for the Scheduler. Abstracting over the clock
+/// lets the scheduler run against a fake clock where time only
+/// moves when told to, instead of the wall clock.
+pub trait Clock {
+    fn now(&self) -> Instant;
+    fn sleep(&self, dur: Duration);
+}
+
+/// The real wall clock, backed by `Instant::now()` and `thread::sleep`
+#[derive(Debug, Default, Copy, Clone)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> Instant {
+        Instant::now()
+    }
+
+    fn sleep(&self, dur: Duration) {
+        thread::sleep(dur);
+    }
+}
+
+/// A manually advanced clock. Sleeping does not block, it simply
+/// moves the clock forward by the requested amount.
+#[derive(Debug)]
+pub struct MockClock {
+    now: Cell<Instant>,
+}
+
+impl MockClock {
+    pub fn new() -> MockClock {
+        MockClock {
+            now: Cell::new(Instant::now()),
+        }
+    }
+
+    /// Move the clock forward
+    pub fn advance(&self, dur: Duration) {
+        self.now.set(self.now.get() + dur);
+    }
+}
+
+impl Default for MockClock {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Clock for MockClock {
+    fn now(&self) -> Instant {
+        self.now.get()
+    }
+
+    fn sleep(&self, dur: Duration) {
+        self.advance(dur);
+    }
+}
+
+/// Where a tick falls as (bar, beat, tick_in_beat), all counted from
+/// zero, with a bar `bar_ticks` long. Bars that aren't a whole number
+/// of beats (say a 7-step pattern of eighth notes) end on a short
+/// beat, and the next bar starts again on beat 0.
+pub fn bar_position(tick: usize, bar_ticks: usize, ticks_per_beat: usize) -> (usize, usize, usize) {
+    let (bar_ticks, ticks_per_beat) = (bar_ticks.max(1), ticks_per_beat.max(1));
+    let in_bar = tick % bar_ticks;
+    (tick / bar_ticks, in_bar / ticks_per_beat, in_bar % ticks_per_beat)
+}
+
+/// Jobs and queued messages a new scheduler has room for
+/// before it needs to grow
+const JOB_CAPACITY: usize = 100;
+
+/// A generic Job container shim to be stored in the scheduler
+/// `due` is the tick it next fires on, `mt` the ticks between firings
+/// and `first` the ticks before its first firing, offset included.
+/// `seq` tells a live job apart from a removed one that used its slot.
+#[derive(Debug)]
+pub struct Job<T> {
+    due: usize,
+    mt: usize,
+    first: usize,
+    once: bool,
+    seq: usize,
+    msg: T,
+}
+
+/// Handle returned when scheduling a job, used to remove it later
+/// Carries the job's `seq`, so a handle kept past its job's end
+/// can't remove a newer job that has since taken the same slot.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct JobId {
+    slot: usize,
+    seq: usize,
+}
+
+/// A Scheduler layout. Contains tick rate, tick duration, timing
+/// and the jobs/queue system. `last_time` is the deadline of the
+/// most recent tick, not the moment it was actually processed.
+/// Jobs are kept in a min-heap on (due tick, slot), so a tick only
+/// touches the jobs that fire on it.
+pub struct Scheduler<T, C: Clock = SystemClock> {
+    clock: C,
+    tick_duration: Duration,
+    ramp: Option<(Duration, usize)>,
+    swing: f64,
+    tick: usize,
+    last_time: Instant,
+    behind: Duration,
+    jobs: Vec<Option<Job<T>>>,
+    heap: BinaryHeap<Reverse<(usize, usize, usize)>>,
+    next_seq: usize,
+    queue: Vec<T>,
+    #[cfg(test)]
+    pops: usize,
+}
+
+impl<T: Clone> Scheduler<T> {
+    /// Create a new scheduler running on the system clock
+    pub fn new() -> Scheduler<T> {
+        Scheduler::with_clock(SystemClock)
+    }
+}
+
+/// Scheduler implementation. The item to be used must implement Clone
+/// For debugging, add `+ std::fmt::Debug`
+impl<T: Clone, C: Clock> Scheduler<T, C> {
+    /// Create a new scheduler with room for `JOB_CAPACITY` jobs and
+    /// queued messages, driven by the given clock
+    pub fn with_clock(clock: C) -> Scheduler<T, C> {
+        let jobs = Vec::with_capacity(JOB_CAPACITY);
+        let queue = Vec::with_capacity(JOB_CAPACITY);
+        let last_time = clock.now();
+        Scheduler {
+            clock,
+            tick_duration: Duration::new(0, 0),
+            ramp: None,
+            swing: 0.5,
+            tick: 0,
+            last_time,
+            behind: Duration::new(0, 0),
+            jobs,
+            heap: BinaryHeap::with_capacity(JOB_CAPACITY),
+            next_seq: 0,
+            queue,
+            #[cfg(test)]
+            pops: 0,
+        }
+    }
+
+    /// Check if the queue has events waiting
+    pub fn has_events(&self) -> bool {
+        !self.queue.is_empty()
+    }
+
+    /// Clear the job queue
+    pub fn clear_queue(&mut self) {
+        // delete all items from queue
+        self.queue.clear();
+    }
+
+    /// Take every queued message, leaving the queue empty
+    pub fn take_events(&mut self) -> Vec<T> {
+        std::mem::take(&mut self.queue)
+    }
+
+    /// Store a job in the first free slot, growing the list if needed,
+    /// and queue it on the heap. Jobs with a zero period never fire,
+    /// so they're stored but never queued.
+    fn insert(&mut self, mut job: Job<T>) -> JobId {
+        let (due, mt, seq) = (job.due, job.mt, self.next_seq);
+        job.seq = seq;
+        self.next_seq += 1;
+        let slot = match self.jobs.iter().position(|slot| slot.is_none()) {
+            Some(slot) => {
+                self.jobs[slot] = Some(job);
+                slot
+            }
+            None => {
+                self.jobs.push(Some(job));
+                self.jobs.len() - 1
+            }
+        };
+        if mt > 0 {
+            self.heap.push(Reverse((due, slot, seq)));
+        }
+        JobId { slot, seq }
+    }
+
+    /// Schedule a job to be executed every N ticks
+    /// Returns a JobId which can be passed to `remove()`
+    pub fn interval(&mut self, tick_amt: usize, msg: T) -> JobId {
+        self.interval_with_offset(tick_amt, 0, msg)
+    }
+
+    /// Schedule a job to be executed every N ticks, phase-shifted
+    /// by `offset` ticks relative to a plain `interval()` job
+    /// of the same period. Offsets wrap around the period.
+    pub fn interval_with_offset(&mut self, tick_amt: usize, offset: usize, msg: T) -> JobId {
+        let ct = match tick_amt {
+            0 => 0,
+            _ => (tick_amt - offset % tick_amt) % tick_amt,
+        };
+        self.insert(Job {
+            due: self.tick + tick_amt - ct,
+            mt: tick_amt,
+            first: tick_amt - ct,
+            once: false,
+            seq: 0,
+            msg,
+        })
+    }
+
+    /// Schedule a job to be executed once after N ticks
+    /// The job removes itself after firing, freeing its slot
+    /// Zero ticks fires on the next tick, like one would
+    pub fn after(&mut self, tick_amt: usize, msg: T) -> JobId {
+        let tick_amt = tick_amt.max(1);
+        self.insert(Job {
+            due: self.tick + tick_amt,
+            mt: tick_amt,
+            first: tick_amt,
+            once: true,
+            seq: 0,
+            msg,
+        })
+    }
+
+    /// Deactivate a job so it no longer fires
+    /// The slot is left empty so other JobIds stay valid, and its
+    /// heap entry is dropped once it comes up
+    /// Returns false if there was no live job with that id, including
+    /// when its slot now belongs to a newer job
+    pub fn remove(&mut self, id: JobId) -> bool {
+        match self.jobs.get_mut(id.slot) {
+            Some(slot) if slot.as_ref().is_some_and(|job| job.seq == id.seq) => {
+                *slot = None;
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Calculate a schedule rate based on BPM against microseconds
+    /// Start with a minute (in us), divide by ticks x BPM
+    /// Non-positive values are rejected, since they would otherwise
+    /// produce a zero-length tick and spin the CPU
+    fn rate_duration(bpm: i32, num_ticks: i32) -> Result<Duration, String> {
+        if bpm <= 0 || num_ticks <= 0 {
+            return Err(format!(
+                "Invalid scheduler rate: bpm={}, ticks={}",
+                bpm, num_ticks
+            ));
+        }
+        let ms = 60000000.0 / (bpm as f64 * num_ticks as f64);
+        if ms < 1.0 {
+            return Err(format!(
+                "Scheduler rate too fast: bpm={}, ticks={}",
+                bpm, num_ticks
+            ));
+        }
+        Ok(Duration::from_micros(ms as u64))
+    }
+
+    /// Set the tick duration from a BPM and a number of ticks per beat
+    /// Cancels any tempo ramp in progress
+    pub fn set_rate(&mut self, bpm: i32, num_ticks: i32) -> Result<(), String> {
+        self.tick_duration = Self::rate_duration(bpm, num_ticks)?;
+        self.ramp = None;
+        Ok(())
+    }
+
+    /// Linearly move the tick duration towards a new BPM over the
+    /// given number of ticks. Each tick nudges the duration by an
+    /// even step until the target rate is reached exactly.
+    pub fn ramp_to(&mut self, target_bpm: i32, num_ticks: i32, over_ticks: usize) -> Result<(), String> {
+        let target = Self::rate_duration(target_bpm, num_ticks)?;
+        match over_ticks {
+            0 => {
+                self.tick_duration = target;
+                self.ramp = None;
+            }
+            _ => self.ramp = Some((target, over_ticks)),
+        }
+        Ok(())
+    }
+
+    /// Step an active tempo ramp forward by one tick
+    fn step_ramp(&mut self) {
+        if let Some((target, remaining)) = self.ramp {
+            if remaining <= 1 {
+                self.tick_duration = target;
+                self.ramp = None;
+                return;
+            }
+            let cur = self.tick_duration.as_nanos() as i128;
+            let goal = target.as_nanos() as i128;
+            let next = cur + (goal - cur) / remaining as i128;
+            self.tick_duration = Duration::from_nanos(next as u64);
+            self.ramp = Some((target, remaining - 1));
+        }
+    }
+
+    /// Advance by one tick, pushing messages onto the queue for any
+    /// job that comes due, in job id order. Does not sleep, so it can be driven
+    /// from an external event loop alongside `next_wake()`.
+    /// Timing is recorded here as well: each tick advances a fixed
+    /// deadline by one tick duration rather than re-reading the clock,
+    /// so sleep jitter never compounds and late ticks catch up.
+    pub fn tick(&mut self) {
+        self.last_time += self.tick_length();
+        self.tick += 1;
+        self.behind = self.clock.now().saturating_duration_since(self.last_time);
+        self.step_ramp();
+
+        while let Some(Reverse((due, id, seq))) = self.heap.peek().copied() {
+            if due > self.tick {
+                break;
+            }
+            self.heap.pop();
+            #[cfg(test)]
+            {
+                self.pops += 1;
+            }
+            let slot = &mut self.jobs[id];
+            let job = match slot {
+                Some(job) if job.seq == seq => job,
+                // removed, or the slot was reused by a newer job
+                _ => continue,
+            };
+            self.queue.push(job.msg.clone());
+            match job.once {
+                true => *slot = None,
+                _ => {
+                    job.due += job.mt;
+                    self.heap.push(Reverse((job.due, id, seq)));
+                }
+            }
+        }
+    }
+
+    /// Forget any time the scheduler has fallen behind, so the next
+    /// tick is due one tick from now instead of a burst of late ticks
+    /// catching up. For after a long stall, e.g. a blocking reconnect.
+    pub fn resync(&mut self) {
+        self.last_time = self.clock.now();
+        self.behind = Duration::new(0, 0);
+    }
+
+    /// How long until the next tick is due
+    /// Returns zero when we are already behind schedule
+    pub fn next_wake(&self) -> Duration {
+        let deadline = self.last_time + self.tick_length();
+        deadline.saturating_duration_since(self.clock.now())
+    }
+
+    /// Set the swing ratio applied to pairs of ticks
+    /// 0.5 is straight timing; higher values lengthen the first tick
+    /// of each pair and shorten the second by the same amount, so
+    /// every pair still spans exactly two tick durations.
+    /// The ratio is clamped to 0.0..=1.0
+    pub fn set_swing(&mut self, ratio: f64) {
+        if ratio.is_nan() {
+            return;
+        }
+        self.swing = ratio.clamp(0.0, 1.0);
+    }
+
+    /// Length of the upcoming tick once swing is applied
+    fn tick_length(&self) -> Duration {
+        let pair = self.tick_duration * 2;
+        match self.tick % 2 == 1 {
+            false => pair.mul_f64(self.swing),
+            _ => pair.saturating_sub(pair.mul_f64(self.swing)),
+        }
+    }
+
+    /// Update will increase the ticks by one
+    /// In order to make sure we are sleeping the thread consistently,
+    /// we need to calculate our current timestamps to ensure
+    /// we can wait a correct amount of time. To do this we calculate
+    /// a delta and sleep for the delta, which will keep us in lockstep
+    /// with our target BPM, to ensure all jobs are executed
+    /// correctly with their respective time measures.
+    /// This is a blocking convenience wrapper over `tick()`
+    /// and `next_wake()`.
+    pub fn update(&mut self) {
+        self.tick();
+        self.clock.sleep(self.next_wake());
+    }
+
+    /// Number of ticks elapsed since creation or the last reset
+    pub fn current_tick(&self) -> usize {
+        self.tick
+    }
+
+    /// The current tick as (bar, beat, tick_in_beat), see `bar_position()`
+    pub fn position(&self, bar_ticks: usize, ticks_per_beat: usize) -> (usize, usize, usize) {
+        bar_position(self.tick, bar_ticks, ticks_per_beat)
+    }
+
+    /// Reset the tick counter and every job's counter to zero,
+    /// re-phasing all jobs to start counting from this tick
+    /// Offset jobs keep their offset against the new count
+    pub fn reset_ticks(&mut self) {
+        self.tick = 0;
+        self.heap.clear();
+        for (id, slot) in self.jobs.iter_mut().enumerate() {
+            if let Some(job) = slot {
+                job.due = job.first;
+                if job.mt > 0 {
+                    self.heap.push(Reverse((job.due, id, job.seq)));
+                }
+            }
+        }
+    }
+
+    /// The nearest whole number of ticks covering a duration at the
+    /// current rate, for scheduling jobs in real time with `after()`
+    pub fn ticks_for(&self, dur: Duration) -> usize {
+        match self.tick_duration.as_nanos() {
+            0 => 0,
+            tick => ((dur.as_nanos() + tick / 2) / tick) as usize,
+        }
+    }
+
+    /// Access the clock driving this scheduler
+    pub fn clock(&self) -> &C {
+        &self.clock
+    }
+
+    /// How far past its deadline the last tick was processed
+    pub fn behind(&self) -> Duration {
+        self.behind
+    }
+}
+
+impl<T: Clone> Default for Scheduler<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A scheduler on a mock clock at 120 BPM, 96 ticks a beat
+    fn mock_scheduler() -> Scheduler<u32, MockClock> {
+        let mut sched = Scheduler::with_clock(MockClock::new());
+        sched.set_rate(120, 96).unwrap();
+        sched
+    }
+
+    #[test]
+    fn resync_drops_the_backlog_after_a_stall() {
+        let mut sched = mock_scheduler();
+        sched.interval(1, 0);
+        sched.clock().advance(Duration::from_secs(5));
+        assert_eq!(sched.next_wake(), Duration::ZERO);
+        sched.resync();
+        assert_eq!(sched.behind(), Duration::ZERO);
+        assert!(sched.next_wake() > Duration::ZERO);
+        // one tick later, exactly one tick's worth is due
+        sched.update();
+        assert_eq!(sched.take_events().len(), 1);
+        assert_eq!(sched.behind(), Duration::ZERO);
+    }
+
+    #[test]
+    fn after_zero_fires_on_the_next_tick() {
+        let mut sched = mock_scheduler();
+        let id = sched.after(0, 7);
+        sched.tick();
+        assert_eq!(sched.take_events(), vec![7]);
+        // the slot was freed, so it can't be removed again
+        assert!(!sched.remove(id));
+        for _ in 0..10 {
+            sched.tick();
+        }
+        assert!(!sched.has_events());
+    }
+
+    /// Tick through `ticks` ticks, collecting what fires on each
+    fn run(sched: &mut Scheduler<u32, MockClock>, ticks: usize) -> Vec<Vec<u32>> {
+        (0..ticks)
+            .map(|_| {
+                sched.tick();
+                sched.take_events()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn heap_matches_a_linear_scan() {
+        let mut rng = crate::rng::Rng::new(83);
+        let mut sched = mock_scheduler();
+        // (slot, msg, period, first due tick, once, live after, live until)
+        // per job, scanned the old way
+        let mut jobs = Vec::new();
+        let mut ids = Vec::new();
+        for msg in 0..100 {
+            let period = 1 + rng.below(24);
+            let once = rng.below(4) == 0;
+            let (id, first) = match once {
+                true => (sched.after(period, msg), period),
+                _ => {
+                    let offset = rng.below(2 * period);
+                    let first = period - (period - offset % period) % period;
+                    (sched.interval_with_offset(period, offset, msg), first)
+                }
+            };
+            ids.push(id);
+            jobs.push((id.slot, msg, period, first, once, 0, usize::MAX));
+        }
+
+        let mut fired = run(&mut sched, 50);
+        // remove every 7th job and refill the freed slots, then try
+        // the stale ids again: the new jobs must keep running
+        for i in (0..100).step_by(7) {
+            // one-shots that already fired are gone on their own
+            let (_, _, _, first, once, _, _) = jobs[i];
+            assert_eq!(sched.remove(ids[i]), !(once && first <= 50));
+            jobs[i].6 = 50;
+            let period = jobs[i].2;
+            let msg = 100 + i as u32;
+            let id = sched.interval(period, msg);
+            jobs.push((id.slot, msg, period, 50 + period, false, 50, usize::MAX));
+        }
+        for i in (0..100).step_by(7) {
+            assert!(!sched.remove(ids[i]));
+        }
+        fired.extend(run(&mut sched, 150));
+
+        for (i, got) in fired.iter().enumerate() {
+            let tick = i + 1;
+            let mut due: Vec<&(usize, u32, usize, usize, bool, usize, usize)> = jobs
+                .iter()
+                .filter(|job| job.5 < tick && tick <= job.6)
+                .filter(|(_, _, period, first, once, _, _)| match once {
+                    true => tick == *first,
+                    _ => tick >= *first && (tick - first) % period == 0,
+                })
+                .collect();
+            // jobs due together fire in slot order
+            due.sort_by_key(|job| job.0);
+            let expected: Vec<u32> = due.iter().map(|job| job.1).collect();
+            assert_eq!(got, &expected, "tick {}", tick);
+        }
+
+        // each tick only pops what fires, plus the entries of the
+        // 15 removed jobs, instead of looking at all 100 jobs
+        let fires: usize = fired.iter().map(|f| f.len()).sum();
+        assert!(sched.pops <= fires + 15);
+        assert!(sched.pops < 100 * 200 / 4);
+    }
+
+    #[test]
+    fn a_tick_running_long_does_not_panic() {
+        let mut sched = mock_scheduler();
+        sched.interval(1, 0);
+        sched.tick();
+        // the work for this tick took ten ticks' worth of time
+        sched.clock().advance(sched.tick_duration * 10);
+        assert_eq!(sched.next_wake(), Duration::ZERO);
+        sched.update();
+        assert!(sched.behind() > Duration::ZERO);
+    }
+
+    #[test]
+    fn removing_one_interval_leaves_the_other() {
+        let mut sched = mock_scheduler();
+        let a = sched.interval(2, 1);
+        sched.interval(2, 2);
+        assert!(sched.remove(a));
+        assert!(!sched.remove(a));
+        let fired: Vec<u32> = run(&mut sched, 10).into_iter().flatten().collect();
+        assert_eq!(fired, vec![2; 5]);
+    }
+
+    #[test]
+    fn a_stale_id_leaves_the_new_job_in_its_slot_alone() {
+        let mut sched = mock_scheduler();
+        let old = sched.after(1, 1);
+        sched.tick();
+        assert_eq!(sched.take_events(), vec![1]);
+        // the one-shot freed its slot, and the next job takes it
+        let new = sched.interval(1, 2);
+        assert!(!sched.remove(old));
+        let fired: Vec<u32> = run(&mut sched, 3).into_iter().flatten().collect();
+        assert_eq!(fired, vec![2; 3]);
+        assert!(sched.remove(new));
+    }
+
+    #[test]
+    fn a_one_shot_fires_exactly_once() {
+        let mut sched = mock_scheduler();
+        sched.after(3, 1);
+        let fired = run(&mut sched, 100);
+        assert_eq!(fired[2], vec![1]);
+        assert_eq!(fired.iter().flatten().count(), 1);
+    }
+
+    #[test]
+    fn offset_jobs_never_share_a_tick() {
+        let mut sched = mock_scheduler();
+        sched.interval(4, 0);
+        sched.interval_with_offset(4, 2, 1);
+        for (i, fired) in run(&mut sched, 100).iter().enumerate() {
+            let expected = match (i + 1) % 4 {
+                0 => vec![0],
+                2 => vec![1],
+                _ => vec![],
+            };
+            assert_eq!(fired, &expected);
+        }
+    }
+
+    #[test]
+    fn drift_stays_bounded_under_uneven_work() {
+        let mut rng = crate::rng::Rng::new(8);
+        let mut sched = mock_scheduler();
+        let tick = sched.tick_duration;
+        let start = sched.clock().now();
+        for _ in 0..1000 {
+            // up to just under a tick of work between ticks
+            sched.clock().advance(tick.mul_f64(rng.below(100) as f64 / 100.0));
+            sched.update();
+        }
+        // update() sleeps to the deadline of the tick after the last
+        let elapsed = sched.clock().now() - start;
+        assert!(elapsed.abs_diff(tick * 1001) < tick, "{:?}", elapsed);
+    }
+
+    #[test]
+    fn swung_tick_pairs_still_span_two_ticks() {
+        let mut sched = mock_scheduler();
+        let tick = sched.tick_duration;
+        sched.set_swing(0.66);
+        let start = sched.clock().now();
+        sched.tick();
+        let first = sched.last_time - start;
+        sched.tick();
+        assert!(first > tick);
+        assert_eq!(sched.last_time - start, tick * 2);
+        // out of range ratios are clamped, NaN is ignored
+        sched.set_swing(2.0);
+        assert_eq!(sched.swing, 1.0);
+        sched.set_swing(f64::NAN);
+        assert_eq!(sched.swing, 1.0);
+    }
+
+    #[test]
+    fn a_ramp_lands_exactly_on_the_target() {
+        let mut sched = mock_scheduler();
+        let target = Scheduler::<u32, MockClock>::rate_duration(60, 96).unwrap();
+        sched.ramp_to(60, 96, 10).unwrap();
+        let mut last = sched.tick_duration;
+        for _ in 0..9 {
+            sched.tick();
+            assert!(sched.tick_duration > last);
+            assert!(sched.tick_duration < target);
+            last = sched.tick_duration;
+        }
+        sched.tick();
+        assert_eq!(sched.tick_duration, target);
+        sched.tick();
+        assert_eq!(sched.tick_duration, target);
+    }
+
+    #[test]
+    fn reset_ticks_rephases_every_job() {
+        let mut sched = mock_scheduler();
+        sched.interval(4, 0);
+        run(&mut sched, 3);
+        sched.reset_ticks();
+        assert_eq!(sched.current_tick(), 0);
+        let fired = run(&mut sched, 8);
+        assert!(fired[..3].iter().all(|f| f.is_empty()));
+        assert_eq!(fired[3], vec![0]);
+        assert_eq!(fired[7], vec![0]);
+    }
+
+    #[test]
+    fn reset_ticks_keeps_offsets() {
+        let mut sched = mock_scheduler();
+        sched.interval(4, 0);
+        sched.interval_with_offset(4, 2, 1);
+        run(&mut sched, 5);
+        sched.reset_ticks();
+        let fired = run(&mut sched, 8);
+        assert_eq!(fired[1], vec![1]);
+        assert_eq!(fired[3], vec![0]);
+        assert_eq!(fired[5], vec![1]);
+        assert_eq!(fired[7], vec![0]);
+    }
+
+    #[test]
+    fn bar_positions_count_from_zero() {
+        assert_eq!(bar_position(0, 384, 96), (0, 0, 0));
+        assert_eq!(bar_position(97, 384, 96), (0, 1, 1));
+        assert_eq!(bar_position(384, 384, 96), (1, 0, 0));
+        // a short last beat, then the next bar starts on beat 0
+        assert_eq!(bar_position(330, 336, 96), (0, 3, 42));
+        assert_eq!(bar_position(336, 336, 96), (1, 0, 0));
+        assert_eq!(bar_position(5, 0, 0), (5, 0, 0));
+    }
+}
+
+// end scheduler.rs
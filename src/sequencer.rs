@@ -0,0 +1,212 @@
+// sequencer.rs - a simple note list player
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+use crate::devices::device::MidiIo;
+use crate::scheduler::{Clock, SystemClock};
+use crate::types::{MidiError, MidiRes};
+
+/// Stops a `play()` in progress, e.g. from another thread, since
+/// the sequencer itself is borrowed for as long as it plays
+#[derive(Debug, Clone)]
+pub struct StopHandle(Arc<AtomicBool>);
+
+impl StopHandle {
+    /// Ask playback to stop after the current step
+    pub fn stop(&self) {
+        self.0.store(false, Ordering::SeqCst);
+    }
+}
+
+/// Plays a list of notes one after another at a fixed step length,
+/// either once or looping forever. Each note is released right
+/// before the next one starts, and the last one when playback ends.
+/// `play()` blocks; drive `step()` from your own loop instead to
+/// stay in control, or stop a loop through a `StopHandle`.
+pub struct Sequencer<'a, C: Clock = SystemClock> {
+    pub notes: Vec<u8>,
+    pub step_duration: Duration,
+    pub looping: bool,
+    pub channel: u8,
+    pub vel: u8,
+    output: Box<dyn MidiIo + 'a>,
+    clock: C,
+    position: usize,
+    playing: Arc<AtomicBool>,
+    sounding: Option<u8>,
+}
+
+impl<'a> Sequencer<'a> {
+    pub fn new<D: MidiIo + 'a>(notes: Vec<u8>, step_duration: Duration, output: D, looping: bool) -> Sequencer<'a> {
+        Sequencer::with_clock(notes, step_duration, output, looping, SystemClock)
+    }
+}
+
+impl<'a, C: Clock> Sequencer<'a, C> {
+    /// Same as `new()`, but timed by a custom clock
+    pub fn with_clock<D: MidiIo + 'a>(
+        notes: Vec<u8>,
+        step_duration: Duration,
+        output: D,
+        looping: bool,
+        clock: C,
+    ) -> Sequencer<'a, C> {
+        Sequencer {
+            notes,
+            step_duration,
+            looping,
+            channel: 0,
+            vel: 127,
+            output: Box::new(output),
+            clock,
+            position: 0,
+            playing: Arc::new(AtomicBool::new(false)),
+            sounding: None,
+        }
+    }
+
+    /// Play the next note, releasing the previous one. Never blocks.
+    /// Returns false once a one-shot run has played every note
+    pub fn step(&mut self) -> Result<bool, MidiError> {
+        self.release()?;
+        if self.position >= self.notes.len() {
+            match self.looping && !self.notes.is_empty() {
+                true => self.position = 0,
+                _ => return Ok(false),
+            }
+        }
+        let note = self.notes[self.position];
+        self.output.write_message([0x90 | (self.channel & 0x0F), note, self.vel, 0])?;
+        self.sounding = Some(note);
+        self.position += 1;
+        Ok(true)
+    }
+
+    /// Play from the current position until the notes run out or a
+    /// `StopHandle` is used. Blocks for the length of the run, which
+    /// for a looping run is until it's stopped.
+    pub fn play(&mut self) -> MidiRes {
+        self.playing.store(true, Ordering::SeqCst);
+        while self.is_playing() && self.step()? {
+            self.clock.sleep(self.step_duration);
+        }
+        self.stop()
+    }
+
+    /// Stop playback, release the sounding note and rewind
+    pub fn stop(&mut self) -> MidiRes {
+        self.playing.store(false, Ordering::SeqCst);
+        self.position = 0;
+        self.release()
+    }
+
+    /// A handle that stops `play()` from outside it
+    pub fn stop_handle(&self) -> StopHandle {
+        StopHandle(self.playing.clone())
+    }
+
+    /// Whether `play()` is in progress
+    pub fn is_playing(&self) -> bool {
+        self.playing.load(Ordering::SeqCst)
+    }
+
+    /// The device notes are written to
+    pub fn output(&mut self) -> &mut dyn MidiIo {
+        self.output.as_mut()
+    }
+
+    fn release(&mut self) -> MidiRes {
+        if let Some(note) = self.sounding.take() {
+            self.output.write_message([0x80 | (self.channel & 0x0F), note, 0, 0])?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::devices::mock::MockDevice;
+    use crate::scheduler::MockClock;
+    use std::cell::{Cell, RefCell};
+
+    /// A clock that uses a stop handle once a number of steps
+    /// have been slept through
+    struct StopAfter {
+        clock: MockClock,
+        steps: Cell<usize>,
+        handle: RefCell<Option<StopHandle>>,
+    }
+
+    impl Clock for &StopAfter {
+        fn now(&self) -> std::time::Instant {
+            self.clock.now()
+        }
+
+        fn sleep(&self, dur: Duration) {
+            self.clock.sleep(dur);
+            self.steps.set(self.steps.get().saturating_sub(1));
+            if self.steps.get() == 0 {
+                if let Some(handle) = self.handle.borrow().as_ref() {
+                    handle.stop();
+                }
+            }
+        }
+    }
+
+    fn step() -> Duration {
+        Duration::from_millis(250)
+    }
+
+    #[test]
+    fn one_shot_plays_every_note_once() {
+        let out = MockDevice::new();
+        let mut seq = Sequencer::with_clock(vec![60, 64, 67], step(), out.clone(), false, MockClock::new());
+        seq.play().unwrap();
+        assert_eq!(
+            out.sent(),
+            vec![
+                [0x90, 60, 127, 0],
+                [0x80, 60, 0, 0],
+                [0x90, 64, 127, 0],
+                [0x80, 64, 0, 0],
+                [0x90, 67, 127, 0],
+                [0x80, 67, 0, 0],
+            ]
+        );
+        assert!(!seq.is_playing());
+    }
+
+    #[test]
+    fn stop_handle_ends_a_looping_run() {
+        let out = MockDevice::new();
+        let clock = StopAfter {
+            clock: MockClock::new(),
+            steps: Cell::new(5),
+            handle: RefCell::new(None),
+        };
+        let mut seq = Sequencer::with_clock(vec![60, 62], step(), out.clone(), true, &clock);
+        *clock.handle.borrow_mut() = Some(seq.stop_handle());
+        seq.play().unwrap();
+
+        let ons: Vec<u8> = out.sent().iter().filter(|m| m[0] == 0x90).map(|m| m[1]).collect();
+        assert_eq!(ons, vec![60, 62, 60, 62, 60]);
+        // nothing is left sounding
+        assert_eq!(out.sent().last(), Some(&[0x80, 60, 0, 0]));
+        assert!(!seq.is_playing());
+    }
+
+    #[test]
+    fn step_wraps_when_looping() {
+        let out = MockDevice::new();
+        let mut seq = Sequencer::with_clock(vec![60], step(), out.clone(), true, MockClock::new());
+        seq.channel = 3;
+        assert!(seq.step().unwrap());
+        assert!(seq.step().unwrap());
+        assert_eq!(out.sent(), vec![[0x93, 60, 127, 0], [0x83, 60, 0, 0], [0x93, 60, 127, 0]]);
+    }
+}
+
+// end sequencer.rs
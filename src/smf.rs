@@ -0,0 +1,116 @@
+// smf.rs - a minimal Standard MIDI File writer
+
+use std::fs::File;
+use std::io::{self, Write};
+
+/// A channel message placed at an absolute tick position
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct SmfEvent {
+    pub tick: u32,
+    pub status: u8,
+    pub data1: u8,
+    pub data2: u8,
+}
+
+/// Encode a value as a MIDI variable-length quantity
+/// (7 bits per byte, high bit set on every byte but the last)
+fn write_vlq(out: &mut Vec<u8>, value: u32) {
+    let mut bytes = vec![(value & 0x7F) as u8];
+    let mut rest = value >> 7;
+    while rest > 0 {
+        bytes.push(((rest & 0x7F) as u8) | 0x80);
+        rest >>= 7;
+    }
+    bytes.reverse();
+    out.extend(bytes);
+}
+
+/// Build a type-0 (single track) SMF in memory
+/// `division` is ticks per quarter note, `bpm` sets the tempo meta event.
+/// Events are sorted by tick, with note-offs ahead of note-ons that
+/// share a tick so repeated notes don't swallow each other.
+pub fn type0_bytes(events: &[SmfEvent], division: u16, bpm: u16) -> Vec<u8> {
+    let mut sorted = events.to_vec();
+    sorted.sort_by_key(|e| (e.tick, e.status & 0xF0 == 0x90));
+
+    let mut track: Vec<u8> = Vec::new();
+
+    // tempo meta event, microseconds per quarter note
+    let tempo = 60_000_000 / (bpm.max(1) as u32);
+    track.extend([0x00, 0xFF, 0x51, 0x03]);
+    track.extend(&tempo.to_be_bytes()[1..]);
+
+    let mut last = 0;
+    for e in sorted.iter() {
+        write_vlq(&mut track, e.tick - last);
+        track.extend([e.status, e.data1, e.data2]);
+        last = e.tick;
+    }
+
+    // end of track
+    track.extend([0x00, 0xFF, 0x2F, 0x00]);
+
+    let mut out: Vec<u8> = Vec::new();
+    out.extend(b"MThd");
+    out.extend(6u32.to_be_bytes());
+    out.extend(0u16.to_be_bytes()); // format 0
+    out.extend(1u16.to_be_bytes()); // one track
+    out.extend(division.to_be_bytes());
+    out.extend(b"MTrk");
+    out.extend((track.len() as u32).to_be_bytes());
+    out.extend(track);
+    out
+}
+
+/// Write a type-0 SMF to disk
+pub fn write_type0(path: &str, events: &[SmfEvent], division: u16, bpm: u16) -> io::Result<()> {
+    let mut file = File::create(path)?;
+    file.write_all(&type0_bytes(events, division, bpm))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn vlq(value: u32) -> Vec<u8> {
+        let mut out = Vec::new();
+        write_vlq(&mut out, value);
+        out
+    }
+
+    #[test]
+    fn variable_length_quantities() {
+        assert_eq!(vlq(0), vec![0x00]);
+        assert_eq!(vlq(0x7F), vec![0x7F]);
+        assert_eq!(vlq(0x80), vec![0x81, 0x00]);
+        assert_eq!(vlq(0x3FFF), vec![0xFF, 0x7F]);
+        assert_eq!(vlq(0x0FFF_FFFF), vec![0xFF, 0xFF, 0xFF, 0x7F]);
+    }
+
+    #[test]
+    fn type0_file_layout() {
+        let on = |tick, note| SmfEvent { tick, status: 0x90, data1: note, data2: 100 };
+        let off = |tick, note| SmfEvent { tick, status: 0x80, data1: note, data2: 0 };
+        // out of order, with a note-on sharing a tick with a note-off
+        let events = [on(96, 60), off(96, 60), on(0, 60), off(200, 60)];
+        let bytes = type0_bytes(&events, 96, 120);
+
+        assert_eq!(&bytes[..14], b"MThd\0\0\0\x06\0\0\0\x01\0\x60");
+        assert_eq!(&bytes[14..18], b"MTrk");
+        let track = &bytes[22..];
+        assert_eq!(u32::from_be_bytes(bytes[18..22].try_into().unwrap()) as usize, track.len());
+        assert_eq!(
+            track,
+            &[
+                0x00, 0xFF, 0x51, 0x03, 0x07, 0xA1, 0x20, // 500000us a beat
+                0x00, 0x90, 60, 100,
+                0x60, 0x80, 60, 0,
+                0x00, 0x90, 60, 100,
+                0x68, 0x80, 60, 0,
+                0x00, 0xFF, 0x2F, 0x00,
+            ]
+        );
+    }
+}
+
+// end smf.rs
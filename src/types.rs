@@ -1,4 +1,613 @@
 // types.rs - type aliasing for sanity
 
 extern crate portmidi as pm;
-pub type MidiRes = Result<(), pm::Error>;
+use std::fmt;
+
+/// Everything that can go wrong talking to a MIDI device
+#[derive(Debug)]
+pub enum MidiError {
+    /// An error from PortMidi itself
+    PortMidi(pm::Error),
+    /// No port with this name was found
+    DeviceNotFound(String),
+    /// The device was opened without an input port
+    NoInput,
+    /// The device was opened without an output port
+    NoOutput,
+    /// A message field (named) was given a value it can't hold
+    OutOfRange(&'static str, u8),
+}
+
+impl From<pm::Error> for MidiError {
+    fn from(e: pm::Error) -> MidiError {
+        MidiError::PortMidi(e)
+    }
+}
+
+impl fmt::Display for MidiError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            MidiError::PortMidi(e) => write!(f, "PortMidi error: {}", e),
+            MidiError::DeviceNotFound(name) => write!(f, "No MIDI device named {}", name),
+            MidiError::NoInput => write!(f, "Device has no input port"),
+            MidiError::NoOutput => write!(f, "Device has no output port"),
+            MidiError::OutOfRange(field, value) => write!(f, "{} {} is out of range", field, value),
+        }
+    }
+}
+
+impl std::error::Error for MidiError {}
+
+pub type MidiRes = Result<(), MidiError>;
+
+/// A decoded MIDI channel or real-time message
+/// Channels are 0-based (0..=15)
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum MidiMessage {
+    NoteOff { channel: u8, note: u8, vel: u8 },
+    NoteOn { channel: u8, note: u8, vel: u8 },
+    PolyAftertouch { channel: u8, note: u8, value: u8 },
+    ControlChange { channel: u8, controller: u8, value: u8 },
+    ProgramChange { channel: u8, program: u8 },
+    ChannelPressure { channel: u8, value: u8 },
+    PitchBend { channel: u8, value: u16 },
+    Clock,
+    Start,
+    Continue,
+    Stop,
+}
+
+impl MidiMessage {
+    /// Decode a raw PortMidi event into a typed message
+    /// A note-on with zero velocity is treated as a note-off
+    /// Returns None for message types we don't handle
+    pub fn from_event(e: pm::MidiEvent) -> Option<MidiMessage> {
+        let msg = e.message;
+        MidiMessage::from_bytes(msg.status, msg.data1, msg.data2)
+    }
+
+    /// Decode a message from its status and data bytes
+    pub fn from_bytes(status: u8, data1: u8, data2: u8) -> Option<MidiMessage> {
+        match status {
+            0xF8 => return Some(MidiMessage::Clock),
+            0xFA => return Some(MidiMessage::Start),
+            0xFB => return Some(MidiMessage::Continue),
+            0xFC => return Some(MidiMessage::Stop),
+            _ => {}
+        }
+        let channel = status & 0x0F;
+        match status & 0xF0 {
+            0x80 => Some(MidiMessage::NoteOff {
+                channel,
+                note: data1,
+                vel: data2,
+            }),
+            0x90 if data2 == 0 => Some(MidiMessage::NoteOff {
+                channel,
+                note: data1,
+                vel: 0,
+            }),
+            0x90 => Some(MidiMessage::NoteOn {
+                channel,
+                note: data1,
+                vel: data2,
+            }),
+            0xA0 => Some(MidiMessage::PolyAftertouch {
+                channel,
+                note: data1,
+                value: data2,
+            }),
+            0xB0 => Some(MidiMessage::ControlChange {
+                channel,
+                controller: data1,
+                value: data2,
+            }),
+            0xC0 => Some(MidiMessage::ProgramChange {
+                channel,
+                program: data1,
+            }),
+            0xD0 => Some(MidiMessage::ChannelPressure {
+                channel,
+                value: data1,
+            }),
+            0xE0 => Some(MidiMessage::PitchBend {
+                channel,
+                value: (data1 as u16 & 0x7F) | ((data2 as u16 & 0x7F) << 7),
+            }),
+            _ => None,
+        }
+    }
+
+    /// Encode the message as raw bytes for `write_message`
+    pub fn to_bytes(&self) -> [u8; 4] {
+        match *self {
+            MidiMessage::NoteOff { channel, note, vel } => [0x80 | channel, note, vel, 0],
+            MidiMessage::NoteOn { channel, note, vel } => [0x90 | channel, note, vel, 0],
+            MidiMessage::PolyAftertouch { channel, note, value } => [0xA0 | channel, note, value, 0],
+            MidiMessage::ControlChange {
+                channel,
+                controller,
+                value,
+            } => [0xB0 | channel, controller, value, 0],
+            MidiMessage::ProgramChange { channel, program } => [0xC0 | channel, program, 0, 0],
+            MidiMessage::ChannelPressure { channel, value } => [0xD0 | channel, value, 0, 0],
+            MidiMessage::PitchBend { channel, value } => {
+                [0xE0 | channel, (value & 0x7F) as u8, ((value >> 7) & 0x7F) as u8, 0]
+            }
+            MidiMessage::Clock => [0xF8, 0, 0, 0],
+            MidiMessage::Start => [0xFA, 0, 0, 0],
+            MidiMessage::Continue => [0xFB, 0, 0, 0],
+            MidiMessage::Stop => [0xFC, 0, 0, 0],
+        }
+    }
+}
+
+/// Decodes a stream of events with support for running status,
+/// where a device skips the status byte when it repeats. An event
+/// that starts with a data byte (< 0x80) reuses the last channel
+/// status seen, shifting its bytes along by one.
+#[derive(Debug, Default, Clone)]
+pub struct MidiParser {
+    last_status: Option<u8>,
+}
+
+impl MidiParser {
+    pub fn new() -> MidiParser {
+        MidiParser::default()
+    }
+
+    /// Decode the next event in the stream
+    /// Real-time messages pass through without touching the running
+    /// status, other system messages cancel it
+    pub fn parse(&mut self, e: pm::MidiEvent) -> Option<MidiMessage> {
+        let msg = e.message;
+        match msg.status {
+            0xF8..=0xFF => {}
+            0xF0..=0xF7 => self.last_status = None,
+            0x80..=0xEF => self.last_status = Some(msg.status),
+            _ => {
+                let status = self.last_status?;
+                return MidiMessage::from_bytes(status, msg.status, msg.data1);
+            }
+        }
+        MidiMessage::from_bytes(msg.status, msg.data1, msg.data2)
+    }
+}
+
+const NOTE_NAMES: [&str; 12] = [
+    "C", "C#", "D", "D#", "E", "F", "F#", "G", "G#", "A", "A#", "B",
+];
+
+/// Parse a note name like "C4", "C#4" or "Eb3" into a MIDI number
+/// Uses the convention that C4 is 60, so octaves run from -1 to 9
+/// Returns None for malformed names or notes outside 0..=127
+pub fn note_to_midi(name: &str) -> Option<u8> {
+    let mut chars = name.chars();
+    let base: i32 = match chars.next()?.to_ascii_uppercase() {
+        'C' => 0,
+        'D' => 2,
+        'E' => 4,
+        'F' => 5,
+        'G' => 7,
+        'A' => 9,
+        'B' => 11,
+        _ => return None,
+    };
+    let rest = chars.as_str();
+    let (accidental, octave) = match rest.chars().next()? {
+        '#' => (1, &rest[1..]),
+        'b' => (-1, &rest[1..]),
+        _ => (0, rest),
+    };
+    let octave: i32 = octave.parse().ok()?;
+    if !(-1..=9).contains(&octave) {
+        return None;
+    }
+    match (octave + 1) * 12 + base + accidental {
+        n @ 0..=127 => Some(n as u8),
+        _ => None,
+    }
+}
+
+/// Convert a MIDI number into a note name, using sharps
+/// midi_to_note(60) -> "C4"
+pub fn midi_to_note(n: u8) -> String {
+    let octave = (n / 12) as i32 - 1;
+    format!("{}{}", NOTE_NAMES[(n % 12) as usize], octave)
+}
+
+// heptatonic scales, the two pentatonics and the 12-note chromatic scale
+// Major and Minor are the Ionian and Aeolian modes
+// Custom holds arbitrary semitone offsets from the root
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Scale {
+    Major,
+    Dorian,
+    Phrygian,
+    Lydian,
+    Mixolydian,
+    Minor,
+    Locrian,
+    MajorPentatonic,
+    MinorPentatonic,
+    Chromatic,
+    Custom(Vec<u8>),
+}
+
+// Major: C D E F G A B
+// Dorian: C D Ef F G A Bf
+// Phrygian: C Df Ef F G Af Bf
+// Lydian: C D E Fs G A B
+// Mixolydian: C D E F G A Bf
+// Minor: C D Ef F G Af Bf
+// Locrian: C Df Ef F Gf Af Bf
+// Major pentatonic: C D E G A
+// Minor pentatonic: C Ef F G Bf
+const MAJOR_SCALE: [u8; 7] = [0, 2, 4, 5, 7, 9, 11];
+const DORIAN_SCALE: [u8; 7] = [0, 2, 3, 5, 7, 9, 10];
+const PHRYGIAN_SCALE: [u8; 7] = [0, 1, 3, 5, 7, 8, 10];
+const LYDIAN_SCALE: [u8; 7] = [0, 2, 4, 6, 7, 9, 11];
+const MIXOLYDIAN_SCALE: [u8; 7] = [0, 2, 4, 5, 7, 9, 10];
+const MINOR_SCALE: [u8; 7] = [0, 2, 3, 5, 7, 8, 10];
+const LOCRIAN_SCALE: [u8; 7] = [0, 1, 3, 5, 6, 8, 10];
+const MAJOR_PENTATONIC_SCALE: [u8; 5] = [0, 2, 4, 7, 9];
+const MINOR_PENTATONIC_SCALE: [u8; 5] = [0, 3, 5, 7, 10];
+const CHROMATIC_SCALE: [u8; 12] = [0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11];
+
+// How many built-in scales `Scale::next()` cycles through
+pub const SCALE_COUNT: usize = 10;
+
+impl Scale {
+    /// Build a custom scale from semitone offsets
+    /// Scale::custom(&[0, 2, 3, 6, 7, 8, 11]) -> Hungarian minor
+    pub fn custom(steps: &[u8]) -> Scale {
+        Scale::Custom(steps.to_vec())
+    }
+
+    /// The semitone LUT for this scale
+    pub fn steps(&self) -> &[u8] {
+        match self {
+            Scale::Major => &MAJOR_SCALE,
+            Scale::Dorian => &DORIAN_SCALE,
+            Scale::Phrygian => &PHRYGIAN_SCALE,
+            Scale::Lydian => &LYDIAN_SCALE,
+            Scale::Mixolydian => &MIXOLYDIAN_SCALE,
+            Scale::Minor => &MINOR_SCALE,
+            Scale::Locrian => &LOCRIAN_SCALE,
+            Scale::MajorPentatonic => &MAJOR_PENTATONIC_SCALE,
+            Scale::MinorPentatonic => &MINOR_PENTATONIC_SCALE,
+            Scale::Chromatic => &CHROMATIC_SCALE,
+            Scale::Custom(steps) => steps,
+        }
+    }
+
+    /// Find the built-in scale with these semitone offsets,
+    /// falling back to a custom scale when none match
+    pub fn from_steps(steps: &[u8]) -> Scale {
+        let mut scale = Scale::Major;
+        loop {
+            if scale.steps() == steps {
+                return scale;
+            }
+            scale = scale.next();
+            if scale == Scale::Major {
+                return Scale::custom(steps);
+            }
+        }
+    }
+
+    /// The scale that follows this one when cycling modes
+    /// Custom scales are not part of the cycle and lead back to Major
+    pub fn next(&self) -> Scale {
+        match self {
+            Scale::Major => Scale::Dorian,
+            Scale::Dorian => Scale::Phrygian,
+            Scale::Phrygian => Scale::Lydian,
+            Scale::Lydian => Scale::Mixolydian,
+            Scale::Mixolydian => Scale::Minor,
+            Scale::Minor => Scale::Locrian,
+            Scale::Locrian => Scale::MajorPentatonic,
+            Scale::MajorPentatonic => Scale::MinorPentatonic,
+            Scale::MinorPentatonic => Scale::Chromatic,
+            Scale::Chromatic => Scale::Major,
+            Scale::Custom(_) => Scale::Major,
+        }
+    }
+}
+
+/// Convert a MIDI note and a Scale to a scale-based MIDI message
+/// Uses the scale's LUT to find the semitone offset
+/// Column values 1..=N map to the 1st..Nth scale degree,
+/// where N is the number of notes in the scale
+pub fn calc_note(note: u8, scale: &Scale) -> Option<u8> {
+    match note {
+        0 => None,
+        _ => scale.steps().get((note - 1) as usize).copied(),
+    }
+}
+
+/// Semitone offsets of the triad built on a column value's scale degree:
+/// the degree itself plus the degrees two and four above it.
+/// Degrees past the end of the scale wrap into the next octave.
+pub fn calc_triad(note: u8, scale: &Scale) -> Option<[u8; 3]> {
+    let steps = scale.steps();
+    if note == 0 || (note as usize) > steps.len() {
+        return None;
+    }
+    let degree = (note - 1) as usize;
+    let at = |d: usize| steps[d % steps.len()] + (12 * (d / steps.len())) as u8;
+    Some([at(degree), at(degree + 2), at(degree + 4)])
+}
+
+/// Snap a MIDI note to the nearest degree of a scale in the given key
+/// (root as a semitone, 0 = C). Ties go to the lower degree, and a
+/// note that would snap above 127 falls back to the degree below it.
+pub fn quantize_to_scale(note: u8, scale: &Scale, root: u8) -> u8 {
+    let steps = scale.steps();
+    if steps.is_empty() {
+        return note;
+    }
+    let note = note.min(127) as i32;
+    let root = (root % 12) as i32;
+    let base = note - (note - root).rem_euclid(12);
+
+    // candidates one octave either side so the nearest wraps correctly
+    let mut best = note;
+    let mut best_dist = i32::MAX;
+    for octave in [-12, 0, 12] {
+        for step in steps {
+            let cand = base + octave + *step as i32;
+            let dist = (cand - note).abs();
+            if (0..=127).contains(&cand) && (dist < best_dist || (dist == best_dist && cand < best)) {
+                best = cand;
+                best_dist = dist;
+            }
+        }
+    }
+    best as u8
+}
+
+/// How column velocities are bent before they're sent out
+/// Linear leaves them alone, Exponential pushes low velocities
+/// further down and Logarithmic lifts them up
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default)]
+pub enum VelocityCurve {
+    #[default]
+    Linear,
+    Exponential,
+    Logarithmic,
+}
+
+impl VelocityCurve {
+    pub fn from_name(name: &str) -> Option<VelocityCurve> {
+        match name.to_lowercase().as_str() {
+            "linear" | "lin" => Some(VelocityCurve::Linear),
+            "exponential" | "exp" => Some(VelocityCurve::Exponential),
+            "logarithmic" | "log" => Some(VelocityCurve::Logarithmic),
+            _ => None,
+        }
+    }
+}
+
+/// Apply a velocity curve to a MIDI velocity
+/// 0 and 127 map to themselves on every curve, and any other
+/// velocity stays at least 1 so a note-on never turns into a note-off
+pub fn shape_velocity(v: u8, curve: &VelocityCurve) -> u8 {
+    let x = v.min(127) as f32 / 127.0;
+    let y = match curve {
+        VelocityCurve::Linear => return v.min(127),
+        VelocityCurve::Exponential => x * x,
+        VelocityCurve::Logarithmic => x.sqrt(),
+    };
+    match v {
+        0 => 0,
+        _ => ((y * 127.0).round() as u8).max(1),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CURVES: [VelocityCurve; 3] = [
+        VelocityCurve::Linear,
+        VelocityCurve::Exponential,
+        VelocityCurve::Logarithmic,
+    ];
+
+    #[test]
+    fn velocity_curves_keep_their_endpoints() {
+        for curve in CURVES.iter() {
+            assert_eq!(shape_velocity(0, curve), 0);
+            assert_eq!(shape_velocity(127, curve), 127);
+            assert_eq!(shape_velocity(200, curve), 127);
+        }
+    }
+
+    #[test]
+    fn velocity_curves_bend_the_middle() {
+        assert_eq!(shape_velocity(64, &VelocityCurve::Linear), 64);
+        assert_eq!(shape_velocity(64, &VelocityCurve::Exponential), 32);
+        assert_eq!(shape_velocity(64, &VelocityCurve::Logarithmic), 90);
+    }
+
+    #[test]
+    fn exponential_compresses_soft_notes_without_silencing_them() {
+        for v in 1..=127 {
+            let shaped = shape_velocity(v, &VelocityCurve::Exponential);
+            assert!(shaped >= 1 && shaped <= v, "{} -> {}", v, shaped);
+        }
+        assert_eq!(shape_velocity(1, &VelocityCurve::Exponential), 1);
+    }
+
+    #[test]
+    fn velocity_curves_never_reverse_order() {
+        for curve in CURVES.iter() {
+            for v in 1..=127 {
+                assert!(shape_velocity(v, curve) >= shape_velocity(v - 1, curve));
+            }
+        }
+    }
+
+    #[test]
+    fn velocity_curve_names() {
+        assert_eq!(VelocityCurve::from_name("EXP"), Some(VelocityCurve::Exponential));
+        assert_eq!(VelocityCurve::from_name("log"), Some(VelocityCurve::Logarithmic));
+        assert_eq!(VelocityCurve::from_name("linear"), Some(VelocityCurve::Linear));
+        assert_eq!(VelocityCurve::from_name("cubic"), None);
+    }
+
+    #[test]
+    fn messages_round_trip_through_bytes() {
+        let msgs = [
+            MidiMessage::NoteOff { channel: 1, note: 60, vel: 64 },
+            MidiMessage::NoteOn { channel: 15, note: 127, vel: 1 },
+            MidiMessage::PolyAftertouch { channel: 2, note: 40, value: 90 },
+            MidiMessage::ControlChange { channel: 0, controller: 74, value: 127 },
+            MidiMessage::ProgramChange { channel: 9, program: 5 },
+            MidiMessage::ChannelPressure { channel: 3, value: 12 },
+            MidiMessage::PitchBend { channel: 4, value: 16383 },
+            MidiMessage::Clock,
+            MidiMessage::Start,
+            MidiMessage::Continue,
+            MidiMessage::Stop,
+        ];
+        for msg in msgs {
+            let [status, data1, data2, _] = msg.to_bytes();
+            assert_eq!(MidiMessage::from_bytes(status, data1, data2), Some(msg));
+        }
+        assert_eq!(
+            MidiMessage::from_bytes(0x93, 60, 0),
+            Some(MidiMessage::NoteOff { channel: 3, note: 60, vel: 0 })
+        );
+        assert_eq!(
+            MidiMessage::from_bytes(0xE0, 0x00, 0x40),
+            Some(MidiMessage::PitchBend { channel: 0, value: 8192 })
+        );
+        assert_eq!(MidiMessage::from_bytes(0xF0, 0, 0), None);
+    }
+
+    #[test]
+    fn note_names() {
+        assert_eq!(note_to_midi("C4"), Some(60));
+        assert_eq!(note_to_midi("c#4"), Some(61));
+        assert_eq!(note_to_midi("Eb3"), Some(51));
+        assert_eq!(note_to_midi("C-1"), Some(0));
+        assert_eq!(note_to_midi("G9"), Some(127));
+        for bad in ["G#9", "Cb-1", "H4", "C", "C10", "", "#4"] {
+            assert_eq!(note_to_midi(bad), None, "{}", bad);
+        }
+        assert_eq!(midi_to_note(60), "C4");
+        assert_eq!(midi_to_note(0), "C-1");
+        for n in 0..=127 {
+            assert_eq!(note_to_midi(&midi_to_note(n)), Some(n));
+        }
+    }
+
+    #[test]
+    fn scale_degrees() {
+        let major = [0, 2, 4, 5, 7, 9, 11];
+        let minor = [0, 2, 3, 5, 7, 8, 10];
+        for degree in 1..=7u8 {
+            let i = degree as usize - 1;
+            assert_eq!(calc_note(degree, &Scale::Major), Some(major[i]));
+            assert_eq!(calc_note(degree, &Scale::Minor), Some(minor[i]));
+        }
+        assert_eq!(calc_note(0, &Scale::Major), None);
+        assert_eq!(calc_note(8, &Scale::Major), None);
+        assert_eq!(calc_note(5, &Scale::MajorPentatonic), Some(9));
+        assert_eq!(calc_note(6, &Scale::MajorPentatonic), None);
+    }
+
+    #[test]
+    fn scales_cycle_and_round_trip_their_steps() {
+        let mut scale = Scale::Major;
+        for _ in 0..SCALE_COUNT {
+            assert_eq!(Scale::from_steps(scale.steps()), scale);
+            scale = scale.next();
+        }
+        assert_eq!(scale, Scale::Major);
+        let hungarian = Scale::custom(&[0, 2, 3, 6, 7, 8, 11]);
+        assert_eq!(Scale::from_steps(hungarian.steps()), hungarian);
+        assert_eq!(hungarian.next(), Scale::Major);
+    }
+
+    #[test]
+    fn triads_wrap_into_the_next_octave() {
+        assert_eq!(calc_triad(1, &Scale::Major), Some([0, 4, 7]));
+        assert_eq!(calc_triad(2, &Scale::Major), Some([2, 5, 9]));
+        assert_eq!(calc_triad(7, &Scale::Major), Some([11, 14, 17]));
+        assert_eq!(calc_triad(1, &Scale::MinorPentatonic), Some([0, 5, 10]));
+        assert_eq!(calc_triad(0, &Scale::Major), None);
+        assert_eq!(calc_triad(8, &Scale::Major), None);
+    }
+
+    #[test]
+    fn quantizing_snaps_to_the_nearest_degree() {
+        assert_eq!(quantize_to_scale(60, &Scale::Major, 0), 60);
+        // ties go down
+        assert_eq!(quantize_to_scale(61, &Scale::Major, 0), 60);
+        assert_eq!(quantize_to_scale(66, &Scale::Major, 0), 65);
+        // in D major, D# sits between D and E
+        assert_eq!(quantize_to_scale(63, &Scale::Major, 2), 62);
+        // just below a root an octave up
+        assert_eq!(quantize_to_scale(71, &Scale::custom(&[0]), 0), 72);
+        // nothing above 127, so fall back below
+        assert_eq!(quantize_to_scale(127, &Scale::custom(&[0]), 0), 120);
+        assert_eq!(quantize_to_scale(61, &Scale::custom(&[]), 0), 61);
+        for note in 0..=127 {
+            let snapped = quantize_to_scale(note, &Scale::Chromatic, 5);
+            assert_eq!(snapped, note);
+        }
+    }
+
+    fn event(msg: [u8; 4]) -> pm::MidiEvent {
+        pm::MidiEvent {
+            message: pm::MidiMessage::from(msg),
+            timestamp: 0,
+        }
+    }
+
+    #[test]
+    fn parser_follows_running_status() {
+        let mut parser = MidiParser::new();
+        // a data byte with no status yet
+        assert_eq!(parser.parse(event([60, 100, 0, 0])), None);
+        assert_eq!(
+            parser.parse(event([0x91, 60, 100, 0])),
+            Some(MidiMessage::NoteOn { channel: 1, note: 60, vel: 100 })
+        );
+        assert_eq!(
+            parser.parse(event([62, 90, 0, 0])),
+            Some(MidiMessage::NoteOn { channel: 1, note: 62, vel: 90 })
+        );
+        // real-time messages slip in without breaking it
+        assert_eq!(parser.parse(event([0xF8, 0, 0, 0])), Some(MidiMessage::Clock));
+        assert_eq!(
+            parser.parse(event([62, 0, 0, 0])),
+            Some(MidiMessage::NoteOff { channel: 1, note: 62, vel: 0 })
+        );
+        // other system messages cancel it
+        assert_eq!(parser.parse(event([0xF0, 0, 0, 0])), None);
+        assert_eq!(parser.parse(event([64, 100, 0, 0])), None);
+    }
+
+    #[test]
+    fn midi_errors_convert_with_the_question_mark() {
+        fn open() -> MidiRes {
+            let res: Result<(), pm::Error> = Err(pm::Error::NoDefaultDevice);
+            res?;
+            Ok(())
+        }
+        assert!(matches!(open(), Err(MidiError::PortMidi(pm::Error::NoDefaultDevice))));
+        let errors = [
+            (MidiError::from(pm::Error::Invalid), "PortMidi error: Invalid"),
+            (MidiError::DeviceNotFound("Launchpad".into()), "No MIDI device named Launchpad"),
+            (MidiError::NoInput, "Device has no input port"),
+            (MidiError::NoOutput, "Device has no output port"),
+        ];
+        for (e, text) in errors {
+            assert_eq!(e.to_string(), text);
+        }
+    }
+}